@@ -0,0 +1,73 @@
+use super::midi::{Score, UNIT};
+use super::{ChordTrackJsonExporter, Exporter, MidiExporter};
+use crate::model::ast::{Ast, MEASURE_LENGTH};
+use anyhow::Result;
+use serde::Serialize;
+use std::io::Write;
+
+/// ticks per quarter-note beat
+const BEAT: u32 = 4 * UNIT;
+
+#[derive(Debug, Serialize)]
+struct ChordEvent {
+    // 1-origin
+    bar: u32,
+    // 1-origin, in quarter notes
+    beat: f64,
+    root: String,
+    pitches: Vec<String>,
+}
+
+impl Exporter for ChordTrackJsonExporter {
+    fn export(&self, f: &mut impl Write, ast: Ast) -> Result<()> {
+        // only the timeline is used, so the note range settings don't matter
+        let exporter = MidiExporter::default();
+        let mut score = Score::new(&exporter);
+        score.interpret(ast)?;
+        let events = score
+            .chords
+            .iter()
+            .map(|(tick, chord)| {
+                let offset = tick % (MEASURE_LENGTH * UNIT);
+                Ok(ChordEvent {
+                    bar: tick / (MEASURE_LENGTH * UNIT) + 1,
+                    beat: offset as f64 / BEAT as f64 + 1.0,
+                    root: chord.key.to_string(),
+                    pitches: chord
+                        .pitch_classes()?
+                        .into_iter()
+                        .map(|p| p.to_string())
+                        .collect(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        serde_json::to_writer_pretty(&mut *f, &events)?;
+        writeln!(f)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::{ChordTrackJsonExporter, Exporter};
+    use crate::import::{Importer, RechordImporter};
+    use anyhow::Result;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_chord_track() -> Result<()> {
+        let ast = RechordImporter::default().import("C G7 | Am\n")?;
+        let mut buf = vec![];
+        ChordTrackJsonExporter.export(&mut buf, ast)?;
+        let events: Value = serde_json::from_slice(&buf)?;
+        assert_eq!(
+            events,
+            json!([
+                {"bar": 1, "beat": 1.0, "root": "C", "pitches": ["C", "E", "G"]},
+                {"bar": 1, "beat": 3.0, "root": "G", "pitches": ["D", "F", "G", "B"]},
+                {"bar": 2, "beat": 1.0, "root": "A", "pitches": ["C", "E", "A"]},
+            ])
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use super::{DegreeExporter, Exporter};
+use crate::model::{
+    ast::{Ast, ChordNode},
+    modifier::Modifier,
+};
+use std::io::Write;
+
+impl Exporter for DegreeExporter {
+    fn export(&self, f: &mut impl Write, ast: Ast) -> anyhow::Result<()> {
+        let ast = ast.into_degree(self.key);
+        if self.figured_bass {
+            writeln!(f, "{:#}", ast)?;
+        } else {
+            writeln!(f, "{}", ast)?;
+        }
+        Ok(())
+    }
+}
+
+/// returns whether the chord is a seventh chord, false for triads and None for anything else
+pub(super) fn is_seventh(chord: &ChordNode) -> Option<bool> {
+    let mut sevenths = 0;
+    for modifier in &chord.modifiers {
+        match modifier {
+            Modifier::Major(5) | Modifier::Minor(5) | Modifier::Dim | Modifier::Aug => {}
+            Modifier::Flat5th => {}
+            Modifier::Major(7)
+            | Modifier::Minor(7)
+            | Modifier::Dominant(7)
+            | Modifier::MinorMajaor7
+            | Modifier::Aug7
+            | Modifier::Dim7 => sevenths += 1,
+            _ => return None,
+        }
+    }
+    match (sevenths, chord.semitones().ok()?.len()) {
+        (0, 3) => Some(false),
+        (1, 4) => Some(true),
+        _ => None,
+    }
+}
+
+/// returns figured-bass numerals of the chord's inversion, None for chords other than triads and sevenths
+pub(super) fn figure(chord: &ChordNode) -> Option<&'static str> {
+    match (is_seventh(chord)?, chord.inversion().ok()??) {
+        (false, 0) => Some(""),
+        (false, 1) => Some("6"),
+        (false, 2) => Some("6/4"),
+        (true, 0) => Some("7"),
+        (true, 1) => Some("6/5"),
+        (true, 2) => Some("4/3"),
+        (true, 3) => Some("4/2"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        export::{DegreeExporter, Exporter},
+        import::{Importer, RechordImporter},
+        model::pitch::Pitch,
+    };
+    use anyhow::Result;
+
+    fn export(code: &str, figured_bass: bool) -> Result<String> {
+        export_in(Pitch::C, code, figured_bass)
+    }
+
+    fn export_in(key: Pitch, code: &str, figured_bass: bool) -> Result<String> {
+        let ast = RechordImporter::default().import(code)?;
+        let mut buf = vec![];
+        DegreeExporter { key, figured_bass }.export(&mut buf, ast)?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn test_on_chord() -> Result<()> {
+        assert_eq!(export("C/G F/A G/B\n", false)?, "I/V IV/VI V/VII | \n\n");
+        // the bass is taken relative to the key, not to the chord
+        assert_eq!(export_in(Pitch::D, "D/A G/B\n", false)?, "I/V IV/VI | \n\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_figured_bass() -> Result<()> {
+        assert_eq!(export("C C/E C/G\n", true)?, "I I6 I6/4 | \n\n");
+        assert_eq!(export("Dm7/F\n", true)?, "IIm6/5 | \n\n");
+        assert_eq!(export("C/D\n", true)?, "I/II | \n\n");
+        assert_eq!(export("Cmaj7/E Bm7b5/F\n", true)?, "IM6/5 VIIø4/3 | \n\n");
+        assert_eq!(export("Bm7b5 Bm7b5/D\n", true)?, "VIIø7 VIIø6/5 | \n\n");
+        // four tones without a seventh keep their on-chord
+        assert_eq!(export("C6/E Cadd9/E\n", true)?, "I6/III Iadd9/III | \n\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use super::{Exporter, ImprovExporter};
+use crate::model::{
+    ast::{Ast, Node},
+    key::Key,
+    pitch::Pitch,
+};
+use anyhow::Result;
+use std::io::Write;
+
+impl Exporter for ImprovExporter {
+    fn export(&self, f: &mut impl Write, ast: Ast) -> Result<()> {
+        for node in ast {
+            let Node::Chord(node) = node else {
+                continue;
+            };
+            let chord = node.to_chord()?;
+            let Key::Absolute(root) = chord.key else {
+                return Err(anyhow::anyhow!("relative key: {}", chord.key));
+            };
+            let Some(scale) = chord.suggested_scale() else {
+                writeln!(f, "{}: -", node)?;
+                continue;
+            };
+            let notes = scale
+                .semitones(&[1, 2, 3, 4, 5, 6, 7])
+                .into_iter()
+                .map(|s| Pitch::try_from((root as u8 + s) % 12).map(|p| p.to_string()))
+                .collect::<Result<Vec<_>>>()?;
+            writeln!(
+                f,
+                "{}: {} {} ({})",
+                node,
+                root,
+                scale.mode_name(),
+                notes.join(" ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::{Exporter, ImprovExporter};
+    use crate::import::{Importer, RechordImporter};
+    use anyhow::Result;
+
+    #[test]
+    fn test_improv() -> Result<()> {
+        let ast = RechordImporter::default().import("Dm7 G7 Cmaj7 | Caug\n")?;
+        let mut buf = vec![];
+        ImprovExporter.export(&mut buf, ast)?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "Dm7: D Dorian (D E F G A B C)\n\
+             G7: G Mixolydian (G A B C D E F)\n\
+             CM7: C Ionian (C D E F G A B)\n\
+             Caug: -\n"
+        );
+        Ok(())
+    }
+}
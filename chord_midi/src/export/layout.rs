@@ -0,0 +1,165 @@
+use super::{Exporter, LayoutExporter};
+use crate::model::ast::{measure_slots, voices, Ast, Node};
+use anyhow::Result;
+use serde::Serialize;
+use std::io::Write;
+
+/// a symbol to print, `x` is a fraction of its measure's width
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SymbolLayout {
+    pub symbol: String,
+    pub x: f64,
+}
+
+/// a measure of a system, `x` and `width` are fractions of the system's width
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MeasureLayout {
+    /// 0-origin over the whole chart
+    pub index: usize,
+    pub x: f64,
+    pub width: f64,
+    pub symbols: Vec<SymbolLayout>,
+}
+
+/// a line of the chart, barlines are drawn at the edges of its measures
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct System {
+    /// section marker the system starts with
+    pub label: Option<String>,
+    pub measures: Vec<MeasureLayout>,
+}
+
+/// positions of chord symbols and barlines, leaving the page size to the renderer
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChartLayout {
+    pub systems: Vec<System>,
+}
+
+impl ChartLayout {
+    /// groups measures into systems of `bars_per_system`, a marker starts a new system
+    pub fn new(ast: &Ast, bars_per_system: usize) -> Result<ChartLayout> {
+        if bars_per_system == 0 {
+            return Err(anyhow::anyhow!("no bars per system"));
+        }
+        let mut systems: Vec<System> = vec![];
+        let mut label = None;
+        let mut index = 0;
+        for ast in items(ast) {
+            let nodes = match ast {
+                Ast::Marker(marker) => {
+                    label = Some(marker.clone());
+                    continue;
+                }
+                Ast::Measure(nodes, _) => nodes,
+                _ => continue,
+            };
+            match systems.last_mut() {
+                Some(system) if label.is_none() && system.measures.len() < bars_per_system => {
+                    system.measures.push(measure_layout(index, nodes)?)
+                }
+                _ => systems.push(System {
+                    label: label.take(),
+                    measures: vec![measure_layout(index, nodes)?],
+                }),
+            }
+            index += 1;
+        }
+        // measures keep the same width in a short last system
+        for system in &mut systems {
+            for (i, measure) in system.measures.iter_mut().enumerate() {
+                measure.x = i as f64 / bars_per_system as f64;
+                measure.width = 1.0 / bars_per_system as f64;
+            }
+        }
+        Ok(ChartLayout { systems })
+    }
+}
+
+fn items(ast: &Ast) -> Vec<&Ast> {
+    match ast {
+        Ast::Score(score) => score.iter().flat_map(|ast| items(ast)).collect(),
+        ast => vec![ast],
+    }
+}
+
+/// places the symbols of the first voice at the start of their slots
+fn measure_layout(index: usize, nodes: &[Node]) -> Result<MeasureLayout> {
+    let voice = voices(nodes)[0];
+    let slots = measure_slots(voice)? as f64;
+    let mut symbols = vec![];
+    let mut slot = 0.0;
+    for node in voice {
+        let width = node.width() as f64;
+        let members = match node {
+            Node::Tuplet(nodes) => nodes.iter().collect(),
+            node => vec![node],
+        };
+        for (i, member) in members.iter().enumerate() {
+            if matches!(member, Node::Chord(_) | Node::Rest | Node::Repeat) {
+                symbols.push(SymbolLayout {
+                    symbol: member.to_string(),
+                    x: (slot + width * i as f64 / members.len() as f64) / slots,
+                });
+            }
+        }
+        slot += width;
+    }
+    Ok(MeasureLayout {
+        index,
+        x: 0.0,
+        width: 0.0,
+        symbols,
+    })
+}
+
+impl Exporter for LayoutExporter {
+    fn export(&self, f: &mut impl Write, ast: Ast) -> Result<()> {
+        let layout = ChartLayout::new(&ast, self.bars_per_system)?;
+        serde_json::to_writer_pretty(&mut *f, &layout)?;
+        writeln!(f)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChartLayout, SymbolLayout};
+    use crate::import::{Importer, RechordImporter};
+    use anyhow::Result;
+
+    #[test]
+    fn test_layout() -> Result<()> {
+        let code = "C | G | Am | F\n".repeat(4);
+        let ast = RechordImporter::default().import(&code)?;
+        let layout = ChartLayout::new(&ast, 4)?;
+        assert_eq!(layout.systems.len(), 4);
+        for (i, system) in layout.systems.iter().enumerate() {
+            let indices = system.measures.iter().map(|m| m.index).collect::<Vec<_>>();
+            assert_eq!(indices, (4 * i..4 * i + 4).collect::<Vec<_>>());
+            assert_eq!(system.measures[1].x, 0.25);
+        }
+        assert_eq!(
+            layout.systems[0].measures[2].symbols,
+            vec![SymbolLayout {
+                symbol: "Am".to_string(),
+                x: 0.0
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_layout_sections() -> Result<()> {
+        let ast = RechordImporter::default().import("[A]\nC G = Am | F\n[B]\nG\n")?;
+        let layout = ChartLayout::new(&ast, 4)?;
+        assert_eq!(layout.systems.len(), 2);
+        assert_eq!(layout.systems[1].label.as_deref(), Some("B"));
+        let xs = layout.systems[0].measures[0]
+            .symbols
+            .iter()
+            .map(|s| (s.symbol.as_str(), s.x))
+            .collect::<Vec<_>>();
+        assert_eq!(xs, vec![("C", 0.0), ("G", 0.25), ("Am", 0.75)]);
+        Ok(())
+    }
+}
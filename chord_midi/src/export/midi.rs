@@ -1,80 +1,344 @@
 use super::Exporter;
-use super::MidiExporter;
+use super::{Doubling, MidiExporter, VoicingStyle};
 use crate::model::ast::Ast;
-use crate::model::ast::Node;
+use crate::model::ast::{measure_slots, voices, MEASURE_LENGTH};
+use crate::model::ast::{Directive, Node};
 use crate::model::chord::Chord;
 use crate::model::key::Key;
+use crate::model::pitch::{midi_note, Pitch};
 use anyhow::anyhow;
 use anyhow::Result;
-use midi_file::core::{Channel, Clocks, DurationName, GeneralMidi, NoteNumber, Velocity};
-use midi_file::file::{QuartersPerMinute, Track};
-use midi_file::MidiFile;
+use midi_file::core::{Channel, Clocks, DurationName, NoteNumber, Velocity};
+use midi_file::file::{Event, MetaEvent, MicrosecondsPerQuarter, Track};
+use midi_file::{MidiFile, Text};
+use serde::Serialize;
 use std::io::Write;
 
-const UNIT: u32 = 1024 / 4;
+pub(super) const UNIT: u32 = 1024 / 4;
 
-fn into_note_numbers(chord: &Chord) -> Result<Vec<NoteNumber>> {
-    let Key::Absolute(p) = chord.key else {
-        return Err(anyhow!("Relative key is not supported"));
-    };
-    let on = chord.on.clone().and_then(|on| {
-        if let Key::Absolute(on) = on {
-            Some(on)
+impl MidiExporter {
+    /// returns strictly ascending note numbers, so the bass comes first: the on-chord,
+    /// otherwise the lowest tone after inversion
+    pub(crate) fn note_numbers(&self, chord: &Chord) -> Result<Vec<NoteNumber>> {
+        let Key::Absolute(p) = chord.key else {
+            return Err(anyhow!("no key to render {} in", chord.key));
+        };
+        let on = chord.on.clone().and_then(|on| {
+            if let Key::Absolute(on) = on {
+                Some(on)
+            } else {
+                None
+            }
+        });
+        if self.bass_only {
+            let bass = on.unwrap_or(p) as u8;
+            return self.to_note_numbers(vec![midi_note(chord.octave - 1, bass)]);
+        }
+        let mut semitones = chord
+            .semitones
+            .iter()
+            .map(|s| midi_note(chord.octave, p as u8 + *s))
+            .collect::<Vec<_>>();
+        for _ in 0..chord.inversion {
+            let n = semitones.remove(0);
+            semitones.push(n + 12);
+        }
+        // a raised tone may still sit below tensions above the octave
+        semitones.sort();
+        semitones.dedup();
+        if let Some(doubling) = self.doubling {
+            let tone = match doubling {
+                Doubling::Root => p as u8,
+                Doubling::Fifth => (p as u8 + 7) % 12,
+            };
+            // skipped when the tone is missing or doubling exceeds the note range
+            if let Some(top) = semitones.iter().filter(|s| *s % 12 == tone).max() {
+                let doubled = top + 12;
+                if self.offset() + doubled as i16 <= 127 {
+                    semitones.push(doubled);
+                    semitones.sort();
+                }
+            }
+        }
+        if let Some(on) = on {
+            semitones.insert(0, midi_note(chord.octave - 1, on as u8));
+        }
+        self.to_note_numbers(semitones)
+    }
+
+    /// returns how far notes move when C at `middle_c_octave` is 60 rather than C4
+    fn offset(&self) -> i16 {
+        12 * (4 - self.middle_c_octave as i16)
+    }
+
+    /// converts `midi_note` numbers, applying `middle_c_octave`
+    fn to_note_numbers(&self, notes: Vec<u8>) -> Result<Vec<NoteNumber>> {
+        notes
+            .into_iter()
+            .map(|s| match self.offset() + s as i16 {
+                n @ 0..=127 => Ok(NoteNumber::new(n as u8)),
+                n => Err(anyhow!("note number out of range: {}", n)),
+            })
+            .collect()
+    }
+
+    /// returns names like `C4` for note numbers, numbering octaves from `middle_c_octave`
+    pub fn note_names(&self, notes: &[NoteNumber]) -> Result<Vec<String>> {
+        notes
+            .iter()
+            .map(|n| {
+                let pitch = Pitch::try_from(n.get() % 12)?;
+                let octave = (n.get() / 12) as i16 - 5 + self.middle_c_octave as i16;
+                Ok(format!("{}{}", pitch, octave))
+            })
+            .collect()
+    }
+
+    /// returns (onset, duration, chord) of each chord of the first voice in quarter notes.
+    /// a chord lasts until the next one or a rest, grace chords count toward the chord they ornament
+    pub fn chord_timeline(&self, ast: Ast) -> Result<Vec<(f64, f64, Chord)>> {
+        let mut score = Score::new(self);
+        score.interpret(self.resolve(ast))?;
+        let mut starts = vec![];
+        let mut tick = 0;
+        for note in &score.notes {
+            starts.push((tick, note));
+            tick += note.duration;
+        }
+        let quarter = (4 * UNIT) as f64;
+        let onsets = score.chords.iter().map(|(onset, _)| *onset);
+        let ends = onsets.skip(1).chain([u32::MAX]);
+        Ok(score
+            .chords
+            .iter()
+            .zip(ends)
+            .map(|((onset, chord), end)| {
+                let duration = starts
+                    .iter()
+                    .filter(|(start, note)| (*onset..end).contains(start) && note.chord.is_some())
+                    .map(|(_, note)| note.duration)
+                    .sum::<u32>();
+                (
+                    *onset as f64 / quarter,
+                    duration as f64 / quarter,
+                    chord.clone(),
+                )
+            })
+            .collect())
+    }
+
+    /// returns the lowest and highest note numbers of all voices, None without chords
+    pub(crate) fn note_range(&self, ast: Ast) -> Result<Option<(u8, u8)>> {
+        let mut notes = vec![];
+        for voice in self.resolve(ast).into_voices() {
+            let mut score = Score::new(self);
+            score.interpret(voice)?;
+            notes.extend(
+                score
+                    .notes
+                    .into_iter()
+                    .flat_map(|n| n.chord.unwrap_or_default()),
+            );
+        }
+        let notes = notes.iter().map(|n| n.get());
+        Ok(notes.clone().min().zip(notes.max()))
+    }
+
+    /// shifts the chart by whole octaves so the notes this exporter plays fit in `low..=high`,
+    /// as close to the original as possible
+    pub fn fit_range(&self, ast: Ast, low: u8, high: u8) -> Result<Ast> {
+        let Some((min, max)) = self.note_range(ast.clone())? else {
+            return Ok(ast);
+        };
+        let (min, max) = (min as i16, max as i16);
+        let shift = (-10..=10i8)
+            .filter(|k| min + 12 * *k as i16 >= low as i16 && max + 12 * *k as i16 <= high as i16)
+            .min_by_key(|k| k.abs())
+            .ok_or_else(|| anyhow!("{}..={} doesn't fit in {}..={}", min, max, low, high))?;
+        if shift == 0 {
+            return Ok(ast);
+        }
+        let mut score = vec![Box::new(Ast::Directive(Directive::Octave(shift)))];
+        match ast {
+            Ast::Score(items) => score.extend(items),
+            ast => score.push(Box::new(ast)),
+        }
+        Ok(Ast::Score(score))
+    }
+
+    /// moves `tick` by `swing`: the off-beat of each pair of `swing_subdivision`s moves to
+    /// `swing` of the pair, and the ticks between stretch or shrink along with it
+    fn swung(&self, tick: u32) -> u32 {
+        let sub = self.swing_subdivision.sixteenths() * UNIT;
+        let (base, pos) = (tick - tick % (2 * sub), tick % (2 * sub));
+        let off_beat = 2.0 * sub as f64 * self.swing as f64;
+        let pos = pos as f64;
+        let pos = if pos <= sub as f64 {
+            pos * off_beat / sub as f64
         } else {
-            None
+            off_beat + (pos - sub as f64) * (2.0 * sub as f64 - off_beat) / sub as f64
+        };
+        base + pos.round() as u32
+    }
+
+    /// resolves degree chords with `key`
+    fn resolve(&self, ast: Ast) -> Ast {
+        match self.key {
+            Some(key) => ast.into_pitch(key),
+            None => ast,
         }
-    });
-    let mut semitones = chord
-        .semitones
-        .iter()
-        // C4 is 60
-        .map(|s| 12 * chord.octave + (p as u8) + *s)
-        .collect::<Vec<_>>();
-    for _ in 0..chord.inversion {
-        let n = semitones.remove(0);
-        semitones.push(n + 12);
     }
-    if let Some(on) = on {
-        semitones.push(12 * (chord.octave - 1) + (on as u8));
+
+    /// writes `tick: note names` for each sounding chord, for checking the exported MIDI
+    pub fn export_debug_notes(&self, f: &mut impl Write, ast: Ast) -> Result<()> {
+        let mut score = Score::new(self);
+        score.interpret_variations(self.resolve(ast))?;
+        let mut tick = 0;
+        for note in &score.notes {
+            if let Some(chord) = &note.chord {
+                writeln!(f, "{}: {}", tick, self.note_names(chord)?.join(" "))?;
+            }
+            tick += note.duration;
+        }
+        Ok(())
     }
-    Ok(semitones
-        .into_iter()
-        .map(|s| NoteNumber::new(12 + s))
-        .collect())
 }
 
 #[derive(Debug, Clone)]
 struct Note {
     pub chord: Option<Vec<NoteNumber>>,
+    // ticks
     pub duration: u32,
+    pub velocity: Velocity,
 }
 
 impl Note {
-    fn new(chord: Option<Vec<NoteNumber>>, duration: u32) -> Self {
-        Note { chord, duration }
+    fn new(chord: Option<Vec<NoteNumber>>, duration: u32, velocity: Velocity) -> Self {
+        Note {
+            chord,
+            duration,
+            velocity,
+        }
     }
 }
 
 #[derive(Debug)]
-struct Score {
+pub(super) struct Score<'a> {
+    exporter: &'a MidiExporter,
     notes: Vec<Note>,
+    // (tick, label)
+    markers: Vec<(u32, String)>,
+    // (tick, chord) at each chord onset, including `%`
+    pub(super) chords: Vec<(u32, Chord)>,
     sustain: u32,
     rest: u32,
     pre: Option<Chord>,
+    velocity: Velocity,
+    // +1 while crescendo, -1 while decrescendo
+    hairpin: i8,
+    // (start tick, end tick, from, to) of an `@dynamics` ramp
+    dynamics: Option<(u32, u32, u8, u8)>,
+    // octaves added by `@octave`
+    octave: i8,
+    // state of the voicing generator, None without `voicing_seed`
+    random: Option<u64>,
+    // voicing of the current pass of `variations`
+    style: VoicingStyle,
+    // the last chord was marked `*` and sounds through rests
+    ringing: bool,
+    // the last chord was marked `.` and sounds for half its length
+    staccato: bool,
+    // (tick, quarters per minute) of each `@tempo`
+    tempos: Vec<(u32, f32)>,
+    // velocity of `@vel`, taken by the next chord so a chord sounding over the directive keeps its own
+    next_velocity: Option<Velocity>,
 }
 
-const MEASURE_LENGTH: u32 = 16;
+/// length of a grace chord, stolen from the chord it ornaments
+const GRACE_TICKS: u32 = UNIT / 4;
+
+/// how early a pushed chord arrives, an eighth
+const PUSH_TICKS: u32 = UNIT * 2;
 
-impl Score {
-    fn new() -> Self {
+/// velocity change per chord inside a hairpin
+const HAIRPIN_STEP: i8 = 8;
+
+impl<'a> Score<'a> {
+    pub(super) fn new(exporter: &'a MidiExporter) -> Self {
         Score {
+            exporter,
             notes: vec![],
+            markers: vec![],
+            chords: vec![],
             sustain: 0,
             rest: 0,
             pre: None,
+            velocity: Velocity::default(),
+            hairpin: 0,
+            dynamics: None,
+            octave: 0,
+            random: exporter.voicing_seed,
+            style: VoicingStyle::Block,
+            ringing: false,
+            staccato: false,
+            tempos: vec![],
+            next_velocity: None,
         }
     }
 
+    /// splitmix64, so a seed renders the same voicings on every platform
+    fn next_random(&mut self) -> Option<u64> {
+        let state = self.random.as_mut()?;
+        *state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        Some(z ^ (z >> 31))
+    }
+
+    /// picks a random inversion and maybe adds a 9th when `voicing_seed` is set
+    fn vary(&mut self, mut chord: Chord) -> Chord {
+        let Some(r) = self.next_random() else {
+            return chord;
+        };
+        chord.inversion = (r % chord.semitones.len().max(1) as u64) as u8;
+        // no 9th on chords that already have tensions
+        if (r >> 32) % 2 == 0 && chord.semitones.iter().all(|s| *s < 12) {
+            chord.semitones.insert(14);
+        }
+        chord
+    }
+
+    /// returns the chord voiced in the style of the current pass
+    fn voice(&self, mut chord: Chord) -> Chord {
+        match self.style {
+            VoicingStyle::Block => {}
+            VoicingStyle::Rootless => {
+                if chord.semitones.len() >= 4 {
+                    chord.semitones.remove(&0);
+                    chord.inversion = chord.inversion.min(chord.semitones.len() as u8 - 1);
+                }
+            }
+            VoicingStyle::Drop2 => chord = chord.drop2(),
+            VoicingStyle::Drop3 => chord = chord.drop3(),
+        }
+        chord
+    }
+
+    /// returns the chord shifted by `@octave`
+    fn shift(&self, mut chord: Chord) -> Result<Chord> {
+        match chord.octave as i8 + self.octave {
+            octave @ 1..=10 => chord.octave = octave as u8,
+            octave => return Err(anyhow!("octave out of range: {}", octave)),
+        }
+        Ok(chord)
+    }
+
+    /// returns the tick at which the next node starts
+    fn tick(&self) -> u32 {
+        self.notes.iter().map(|n| n.duration).sum::<u32>() + self.sustain + self.rest
+    }
+
     fn inspect(&self) {
         log::debug!(
             "pre={} sus={}/{}, rest={}/{}",
@@ -83,35 +347,121 @@ impl Score {
                 .map(|c| format!("{:?}", c))
                 .unwrap_or("None".to_string()),
             self.sustain,
-            MEASURE_LENGTH,
+            MEASURE_LENGTH * UNIT,
             self.rest,
-            MEASURE_LENGTH
+            MEASURE_LENGTH * UNIT
         );
     }
 
-    fn interpret_node(&mut self, node: Node, dur: u32) -> Result<()> {
+    /// writes the sounding chord out, a staccato one followed by its silent half
+    fn end_sustain(&mut self) -> Result<()> {
+        let notes = if let Some(pre) = &self.pre {
+            Some(self.exporter.note_numbers(pre)?)
+        } else {
+            None
+        };
+        let sounding = if self.staccato && notes.is_some() {
+            (self.sustain / 2).max(1)
+        } else {
+            self.sustain
+        };
+        self.notes.push(Note::new(notes, sounding, self.velocity));
+        if sounding < self.sustain {
+            self.notes
+                .push(Note::new(None, self.sustain - sounding, self.velocity));
+        }
+        self.sustain = 0;
+        Ok(())
+    }
+
+    fn interpret_node(&mut self, node: Node, mut dur: u32) -> Result<()> {
         self.inspect();
+        match node {
+            Node::Crescendo => {
+                self.hairpin = 1;
+                return Ok(());
+            }
+            Node::Decrescendo => {
+                self.hairpin = -1;
+                return Ok(());
+            }
+            Node::Subdivision(_) | Node::Voice => return Ok(()),
+            Node::Tuplet(nodes) => {
+                let n = nodes.len() as u32;
+                for (i, node) in nodes.into_iter().enumerate() {
+                    let i = i as u32;
+                    self.interpret_node(node, (i + 1) * dur / n - i * dur / n)?;
+                }
+                return Ok(());
+            }
+            Node::Rest if self.ringing && self.sustain != 0 => {
+                self.sustain += dur;
+                return Ok(());
+            }
+            _ => {}
+        }
         if !matches!(node, Node::Sustain) && self.sustain != 0 {
-            let notes = if let Some(pre) = &self.pre {
-                Some(into_note_numbers(pre)?)
-            } else {
-                None
-            };
-            self.notes.push(Note::new(notes, self.sustain));
-            self.sustain = 0;
+            self.end_sustain()?;
         }
         if !matches!(node, Node::Rest) && self.rest != 0 {
-            self.notes.push(Note::new(None, self.rest));
+            self.notes.push(Note::new(None, self.rest, self.velocity));
             self.rest = 0;
         }
         match node {
             Node::Chord(node) => {
                 log::debug!("chord: {}: {:?}", node, node.modifiers);
-                let chord = node.to_chord()?;
+                // the alternate only changes the tones, not the rhythm of the written chord
+                let chord = match &node.alternate {
+                    Some(alternate) if self.exporter.alternates => alternate.to_chord()?,
+                    _ => node.to_chord()?,
+                };
+                let chord = self.shift(chord)?;
+                let chord = self.vary(chord);
+                let chord = self.voice(chord);
+                if node.push {
+                    // the first chord has nothing to steal from
+                    if let Some(pre) = self.notes.last_mut().filter(|n| n.duration > PUSH_TICKS) {
+                        pre.duration -= PUSH_TICKS;
+                        dur += PUSH_TICKS;
+                    }
+                }
+                self.ringing = node.ring;
+                self.staccato = node.staccato;
+                let onset = self.tick();
+                self.chords.push((onset, chord.clone()));
                 self.pre = Some(chord.clone());
                 self.sustain = dur;
+                // a grace never takes more than half of a short chord
+                let grace_ticks = GRACE_TICKS.min(self.sustain / 2);
+                if let Some(grace) = node.grace.as_ref().filter(|_| grace_ticks > 0) {
+                    let notes = self
+                        .exporter
+                        .note_numbers(&self.shift(grace.to_chord()?)?)?;
+                    self.notes
+                        .push(Note::new(Some(notes), grace_ticks, self.velocity));
+                    self.sustain -= grace_ticks;
+                }
+                if let Some(velocity) = self.next_velocity.take() {
+                    self.velocity = velocity;
+                }
+                if self.hairpin != 0 {
+                    let v = self.velocity.get() as i16 + (self.hairpin * HAIRPIN_STEP) as i16;
+                    self.velocity = Velocity::new(v.clamp(1, 127) as u8);
+                }
+                if let Some((start, end, from, to)) = self.dynamics {
+                    let (from, to) = (from as i64, to as i64);
+                    let elapsed = onset.min(end) - start;
+                    let v = from + (to - from) * elapsed as i64 / (end - start) as i64;
+                    self.velocity = Velocity::new(v as u8);
+                    if onset >= end {
+                        self.dynamics = None;
+                    }
+                }
             }
             Node::Repeat => {
+                if let Some(pre) = &self.pre {
+                    self.chords.push((self.tick(), pre.clone()));
+                }
                 self.sustain = dur;
             }
             Node::Sustain => {
@@ -120,45 +470,94 @@ impl Score {
             Node::Rest => {
                 self.rest += dur;
             }
+            Node::Crescendo
+            | Node::Decrescendo
+            | Node::Subdivision(_)
+            | Node::Voice
+            | Node::Tuplet(_) => unreachable!(),
         }
         Ok(())
     }
 
-    fn measure_unit_size(n: usize) -> Result<u32> {
-        let len = match n {
-            1 => 1,
-            2 => 2,
-            3..=4 => 4,
-            5..=8 => 8,
-            9..=16 => 16,
-            _ => {
-                return Err(anyhow::anyhow!("too many nodes: {}", n));
-            }
-        };
-        Ok(MEASURE_LENGTH / len)
+    /// interprets the chart once per style of `variations`, each pass starting after the last
+    pub(super) fn interpret_variations(&mut self, ast: Ast) -> Result<()> {
+        if self.exporter.variations.is_empty() {
+            return self.interpret(ast);
+        }
+        for style in &self.exporter.variations {
+            self.style = *style;
+            self.octave = 0;
+            self.hairpin = 0;
+            self.dynamics = None;
+            self.velocity = Velocity::default();
+            self.next_velocity = None;
+            self.interpret(ast.clone())?;
+        }
+        Ok(())
     }
 
-    fn interpret(&mut self, ast: Ast) -> Result<()> {
+    pub(super) fn interpret(&mut self, ast: Ast) -> Result<()> {
         match ast {
             Ast::Comment(_) => Ok(()),
+            Ast::Directive(Directive::Dynamics { from, to, measures }) => {
+                let start = self.tick();
+                let end = start + measures * MEASURE_LENGTH * UNIT;
+                self.dynamics = Some((start, end, from, to));
+                Ok(())
+            }
+            Ast::Directive(Directive::Bars(_)) => Ok(()),
+            Ast::Directive(directive @ Directive::Tempo { .. }) => {
+                self.tempos.extend(
+                    directive
+                        .quarters_per_minute()
+                        .map(|qpm| (self.tick(), qpm)),
+                );
+                Ok(())
+            }
+            Ast::Directive(Directive::Velocity(velocity)) => {
+                // a fixed velocity ends hairpins and ramps
+                self.hairpin = 0;
+                self.dynamics = None;
+                self.next_velocity = Some(velocity.map_or_else(Velocity::default, Velocity::new));
+                Ok(())
+            }
+            Ast::Directive(Directive::Octave(octave)) => {
+                self.octave += octave;
+                Ok(())
+            }
+            Ast::Marker(label) => {
+                self.markers.push((self.tick(), label));
+                Ok(())
+            }
             Ast::Score(score) => {
                 for node in score.into_iter() {
                     self.interpret(*node)?
                 }
                 if self.sustain != 0 {
-                    let notes = if let Some(pre) = &self.pre {
-                        Some(into_note_numbers(pre)?)
-                    } else {
-                        None
-                    };
-                    self.notes.push(Note::new(notes, self.sustain));
-                    self.sustain = 0;
+                    self.end_sustain()?;
                 }
                 Ok(())
             }
             Ast::Measure(measure, _) => {
-                let dur = Self::measure_unit_size(measure.len()).unwrap();
+                // a hairpin ends at the barline, later chords keep the velocity it reached
+                self.hairpin = 0;
+                // other voices are interpreted by their own score
+                let measure = voices(&measure)[0].to_vec();
+                if let Some(len) = self.exporter.node_length {
+                    for node in measure {
+                        let dur = node.width() as u32 * len * UNIT;
+                        self.interpret_node(node, dur)?;
+                    }
+                    return Ok(());
+                }
+                // slots may not divide the measure evenly, so each ends where the next begins
+                let slots = measure_slots(&measure)?;
+                let length = MEASURE_LENGTH * UNIT;
+                let mut slot = 0;
                 for node in measure {
+                    let width = node.width().max(1) as u32;
+                    let dur = (slot + width) * length / slots - slot * length / slots;
+                    slot += node.width() as u32;
                     self.interpret_node(node, dur)?;
                 }
                 log::debug!("---");
@@ -168,48 +567,1117 @@ impl Score {
     }
 }
 
-fn write_notes(track: &mut Track, ch: Channel, notes: &[NoteNumber], dur: u32, skip: &mut u32) {
-    for (i, n) in notes.iter().enumerate() {
-        track
-            .push_note_on(if i == 0 { *skip } else { 0 }, ch, *n, Velocity::default())
-            .unwrap();
-        *skip = 0;
+#[derive(Debug)]
+enum ScoreEvent {
+    Marker(String),
+    NoteOff(NoteNumber),
+    NoteOn(NoteNumber, Velocity),
+    // sustain pedal (CC64) down or up
+    Pedal(bool),
+    // quarters per minute
+    Tempo(f32),
+}
+
+impl ScoreEvent {
+    /// order of events sharing a tick
+    fn rank(&self) -> u8 {
+        match self {
+            ScoreEvent::Marker(_) | ScoreEvent::Tempo(_) => 0,
+            ScoreEvent::NoteOff(_) => 1,
+            // the pedal is lifted before the next chord and pressed after it
+            ScoreEvent::Pedal(false) => 2,
+            ScoreEvent::NoteOn(_, _) => 3,
+            ScoreEvent::Pedal(true) => 4,
+        }
     }
-    for (i, n) in notes.iter().enumerate() {
-        track
-            .push_note_off(if i == 0 { dur } else { 0 }, ch, *n, Velocity::default())
-            .unwrap();
+}
+
+/// channels of a voice with `split_voices`: soprano, alto, tenor and bass
+const SATB: usize = 4;
+
+/// returns the SATB channel of the `i`th lowest of `n` chord tones. the lowest is the bass,
+/// the highest the soprano and the one below it the alto, the tenor takes any tones left between
+fn satb_slot(i: usize, n: usize) -> usize {
+    match i {
+        0 => 3,
+        i if i == n - 1 => 0,
+        i if i == n - 2 => 1,
+        _ => 2,
+    }
+}
+
+impl<'a> Score<'a> {
+    /// returns the score split into soprano, alto, tenor and bass, markers stay in the soprano
+    fn split_voices(&self) -> Vec<Score<'a>> {
+        (0..SATB)
+            .map(|slot| {
+                let mut score = Score::new(self.exporter);
+                score.notes = self
+                    .notes
+                    .iter()
+                    .map(|note| {
+                        let chord = note.chord.as_ref().map(|chord| {
+                            let mut chord = chord.clone();
+                            chord.sort_by_key(|n| n.get());
+                            let n = chord.len();
+                            chord
+                                .into_iter()
+                                .enumerate()
+                                .filter(|(i, _)| satb_slot(*i, n) == slot)
+                                .map(|(_, note)| note)
+                                .collect::<Vec<_>>()
+                        });
+                        Note {
+                            chord: chord.filter(|chord| !chord.is_empty()),
+                            ..note.clone()
+                        }
+                    })
+                    .collect();
+                if slot == 0 {
+                    score.markers = self.markers.clone();
+                    score.tempos = self.tempos.clone();
+                }
+                score
+            })
+            .collect()
+    }
+}
+
+impl Score<'_> {
+    /// returns (tick, event) sorted by tick
+    fn events(&self) -> Vec<(u32, ScoreEvent)> {
+        let mut events = vec![];
+        let mut tick = 0;
+        let mut strokes = 0;
+        for note in &self.notes {
+            let (start, end) = (
+                self.exporter.swung(tick),
+                self.exporter.swung(tick + note.duration),
+            );
+            if let Some(chord) = &note.chord {
+                let mut strum = chord.clone();
+                strum.sort_by_key(|n| n.get());
+                if self.exporter.alternate_strum && strokes % 2 == 1 {
+                    strum.reverse();
+                }
+                strokes += 1;
+                for (i, n) in strum.into_iter().enumerate() {
+                    // the last note still starts before the chord ends
+                    let delay = (i as u32 * self.exporter.strum_ticks)
+                        .min(end.saturating_sub(start).saturating_sub(1));
+                    events.push((start + delay, ScoreEvent::NoteOn(n, note.velocity)));
+                }
+                for n in chord {
+                    events.push((end, ScoreEvent::NoteOff(*n)));
+                }
+                // rests are never pedaled, so they release the pedal
+                if self.exporter.pedal {
+                    events.push((start, ScoreEvent::Pedal(true)));
+                    events.push((end, ScoreEvent::Pedal(false)));
+                }
+            }
+            tick += note.duration;
+        }
+        for (tick, label) in &self.markers {
+            events.push((
+                self.exporter.swung(*tick),
+                ScoreEvent::Marker(label.clone()),
+            ));
+        }
+        events.sort_by_key(|(tick, e)| (*tick, e.rank()));
+        events
     }
 }
 
+/// returns a set-tempo event, microseconds per quarter keep fractional tempos
+/// that `QuartersPerMinute` would truncate
+fn tempo_event(bpm: f32) -> Result<Event> {
+    if !(bpm.is_finite() && bpm > 0.0) {
+        return Err(anyhow!("invalid bpm: {}", bpm));
+    }
+    let tempo = MicrosecondsPerQuarter::new((60_000_000.0 / bpm as f64).round() as u32);
+    Ok(Event::Meta(MetaEvent::SetTempo(tempo)))
+}
+
+/// returns a CC64 event. midi_file has no constructor for control changes,
+/// so the event is read back from a one-event file
+fn sustain_pedal(channel: Channel, value: u8) -> Result<Event> {
+    let mut bytes = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x01\x00MTrk\x00\x00\x00\x08".to_vec();
+    bytes.extend([
+        0x00,
+        0xb0 | channel.get(),
+        64,
+        value,
+        0x00,
+        0xff,
+        0x2f,
+        0x00,
+    ]);
+    let mfile = MidiFile::read(bytes.as_slice())?;
+    let event = mfile.track(0).and_then(|track| track.events().next());
+    Ok(event
+        .ok_or_else(|| anyhow!("no sustain pedal event"))?
+        .event()
+        .clone())
+}
+
+impl MidiExporter {
+    /// writes scores of voices to their own channels of a track, skipping the percussion channel
+    fn dump(&self, scores: &[Score], f: &mut impl Write) -> Result<()> {
+        let mut mfile = MidiFile::new();
+        let mut track = Track::default();
+        let channels = (0..16).filter(|ch| *ch != 9).map(Channel::new);
+        let channels = channels.take(scores.len()).collect::<Vec<_>>();
+        if channels.len() < scores.len() {
+            return Err(anyhow!("too many voices: {}", scores.len()));
+        }
+        // off-beats outside the pair would move past the next on-beat
+        if !(self.swing > 0.0 && self.swing < 1.0) {
+            return Err(anyhow!("swing must be between 0 and 1: {}", self.swing));
+        }
+
+        for ch in &channels {
+            track.set_general_midi(*ch, self.instrument)?;
+        }
+        track.push_time_signature(0, 6, DurationName::Sixteenth, Clocks::DottedQuarter)?;
+        // every voice reads the same `@tempo`s, the first one's are written
+        let tempos = scores.first().map(|s| s.tempos.clone()).unwrap_or_default();
+        if !tempos.iter().any(|(tick, _)| *tick == 0) {
+            track.push_event(0, tempo_event(self.bpm)?)?;
+        }
+
+        let mut events = scores
+            .iter()
+            .zip(&channels)
+            .flat_map(|(score, ch)| score.events().into_iter().map(|(t, e)| (t, *ch, e)))
+            .collect::<Vec<_>>();
+        events.extend(
+            tempos
+                .into_iter()
+                .map(|(tick, qpm)| (self.swung(tick), channels[0], ScoreEvent::Tempo(qpm))),
+        );
+        events.sort_by_key(|(tick, _, e)| (*tick, e.rank()));
+
+        let release = Velocity::new(self.release_velocity);
+        let mut last = 0;
+        for (tick, ch, event) in events {
+            let delta = tick - last;
+            last = tick;
+            match event {
+                ScoreEvent::Marker(label) => {
+                    track.push_event(delta, Event::Meta(MetaEvent::Marker(Text::new(label))))?
+                }
+                ScoreEvent::Tempo(qpm) => track.push_event(delta, tempo_event(qpm)?)?,
+                ScoreEvent::NoteOff(n) => track.push_note_off(delta, ch, n, release)?,
+                ScoreEvent::NoteOn(n, v) => track.push_note_on(delta, ch, n, v)?,
+                ScoreEvent::Pedal(on) => {
+                    track.push_event(delta, sustain_pedal(ch, if on { 127 } else { 0 })?)?
+                }
+            }
+        }
+
+        mfile.push_track(track)?;
+        mfile.write(f)?;
+        Ok(())
+    }
+}
+
+/// a sounding chord of the interpreted timeline, for frontends playing the chart themselves
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NoteEvent {
+    /// index of the `&` voice
+    pub voice: usize,
+    /// in seconds
+    pub start: f64,
+    /// in seconds
+    pub duration: f64,
+    pub notes: Vec<u8>,
+    pub velocity: u8,
+}
+
+/// returns the chords of all voices sorted by start, rests are left out.
+/// `bpm` holds until the first `@tempo`, and as in the MIDI the first voice's `@tempo`s pace every voice
+pub fn to_note_events(ast: Ast, key: Pitch, bpm: f32) -> Result<Vec<NoteEvent>> {
+    let exporter = MidiExporter::builder().key(key).bpm(bpm).build();
+    let scores = exporter
+        .resolve(ast)
+        .into_voices()
+        .into_iter()
+        .map(|ast| {
+            let mut score = Score::new(&exporter);
+            score.interpret(ast)?;
+            Ok(score)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut tempos = vec![(0, bpm)];
+    tempos.extend(scores.first().into_iter().flat_map(|s| s.tempos.clone()));
+    tempos.sort_by_key(|(tick, _)| *tick);
+    // sums the seconds of each tempo's span up to `tick`
+    let seconds = |tick: u32| {
+        let mut elapsed = 0.0;
+        for (i, (from, qpm)) in tempos
+            .iter()
+            .enumerate()
+            .filter(|(_, (from, _))| *from < tick)
+        {
+            let to = tempos
+                .get(i + 1)
+                .map_or(tick, |(next, _)| (*next).min(tick));
+            elapsed += (to - from) as f64 / (4 * UNIT) as f64 * 60.0 / *qpm as f64;
+        }
+        elapsed
+    };
+    let mut events = vec![];
+    for (voice, score) in scores.into_iter().enumerate() {
+        let mut tick = 0;
+        for note in score.notes {
+            if let Some(notes) = note.chord {
+                events.push(NoteEvent {
+                    voice,
+                    start: seconds(tick),
+                    duration: seconds(tick + note.duration) - seconds(tick),
+                    notes: notes.iter().map(|n| n.get()).collect(),
+                    velocity: note.velocity.get(),
+                });
+            }
+            tick += note.duration;
+        }
+    }
+    events.sort_by(|a, b| a.start.total_cmp(&b.start).then(a.voice.cmp(&b.voice)));
+    Ok(events)
+}
+
+/// returns (pitch-class bitmask, ticks) of each chord and rest of the first voice, for analysis.
+/// bit `n` is pitch class `n` (bit 0 is C), a rest is 0 and a quarter is 1024 ticks
+pub fn to_pcset_sequence(ast: Ast, key: Pitch) -> Result<Vec<(u16, u32)>> {
+    let exporter = MidiExporter::builder().key(key).build();
+    let mut score = Score::new(&exporter);
+    if let Some(voice) = exporter.resolve(ast).into_voices().into_iter().next() {
+        score.interpret(voice)?;
+    }
+    Ok(score
+        .notes
+        .iter()
+        .map(|note| {
+            let notes = note.chord.iter().flatten();
+            let mask = notes.fold(0, |mask, n| mask | 1 << (n.get() % 12));
+            (mask, note.duration)
+        })
+        .collect())
+}
+
 impl Exporter for MidiExporter {
     fn export(&self, f: &mut impl Write, ast: Ast) -> anyhow::Result<()> {
-        let mut score = Score::new();
-        score.interpret(ast)?;
-        dump_notes(f, &score.notes, self.bpm)
+        let scores = self
+            .resolve(ast)
+            .into_voices()
+            .into_iter()
+            .map(|voice| {
+                let mut score = Score::new(self);
+                score.interpret_variations(voice)?;
+                Ok(score)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let scores = if self.split_voices {
+            scores
+                .iter()
+                .flat_map(|score| score.split_voices())
+                .collect()
+        } else {
+            scores
+        };
+        self.dump(&scores, f)
     }
 }
 
-fn dump_notes(f: &mut impl Write, notes: &[Note], bpm: u8) -> Result<()> {
-    let mut mfile = MidiFile::new();
-    let mut track = Track::default();
-    let ch = Channel::new(0);
+#[cfg(test)]
+mod tests {
+    use super::{to_note_events, to_pcset_sequence, Score, GRACE_TICKS, UNIT};
+    use crate::export::{Doubling, Exporter, MidiExporter, NoteValue, VoicingStyle};
+    use crate::import::{Importer, RechordImporter};
+    use crate::model::{
+        ast::ChordNode,
+        ast::{Ast, Directive, MEASURE_LENGTH},
+        chord::Chord,
+        key::Key,
+        modifier::Modifier,
+        pitch::{midi_note, Pitch},
+    };
+    use anyhow::Result;
+    use midi_file::core::{GeneralMidi, Message};
+    use midi_file::file::{Event, MetaEvent};
+    use midi_file::MidiFile;
 
-    track.set_general_midi(ch, GeneralMidi::SynthVoice).unwrap();
-    track.push_time_signature(0, 6, DurationName::Sixteenth, Clocks::DottedQuarter)?;
-    track.push_tempo(0, QuartersPerMinute::new(bpm))?;
+    const EXPORTER: MidiExporter = MidiExporter {
+        bpm: 120.0,
+        middle_c_octave: 4,
+        release_velocity: 72,
+        key: None,
+        instrument: GeneralMidi::SynthVoice,
+        doubling: None,
+        voicing_seed: None,
+        bass_only: false,
+        pedal: false,
+        node_length: None,
+        variations: vec![],
+        split_voices: false,
+        alternates: false,
+        strum_ticks: 0,
+        alternate_strum: false,
+        swing: 0.5,
+        swing_subdivision: NoteValue::Eighth,
+    };
+
+    fn interpret(code: &str) -> Result<Score<'static>> {
+        static DEFAULT: MidiExporter = EXPORTER;
+        let ast = RechordImporter::default().import(code)?;
+        let mut score = Score::new(&DEFAULT);
+        score.interpret(ast)?;
+        Ok(score)
+    }
+
+    /// exports and reads back the track as (absolute tick, event)
+    fn export(exporter: &MidiExporter, code: &str) -> Result<Vec<(u32, Event)>> {
+        let ast = RechordImporter::default().import(code)?;
+        let mut buf = vec![];
+        exporter.export(&mut buf, ast)?;
+        let mfile = MidiFile::read(buf.as_slice())?;
+        let mut tick = 0;
+        Ok(mfile
+            .track(0)
+            .unwrap()
+            .events()
+            .map(|e| {
+                tick += e.delta_time();
+                (tick, e.event().clone())
+            })
+            .collect())
+    }
+
+    #[test]
+    fn test_hairpin() -> Result<()> {
+        let velocities = |code| -> Result<Vec<u8>> {
+            let score = interpret(code)?;
+            Ok(score.notes.iter().map(|n| n.velocity.get()).collect())
+        };
+        assert_eq!(
+            velocities("C < D E F | G > A B\n")?,
+            vec![72, 80, 88, 96, 96, 88, 80]
+        );
+        // velocity stays flat after the hairpin's measure
+        assert_eq!(
+            velocities("C < G > Am | F | F | F\n")?,
+            vec![72, 80, 72, 72, 72, 72]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_root_note_number() -> Result<()> {
+        let mut chord = Chord::new(5, 0, Key::Absolute(Pitch::C));
+        chord.modify(&Modifier::Major(5))?;
+        assert_eq!(
+            EXPORTER.note_numbers(&chord)?[0].get(),
+            chord.root_note_number()?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_inversion_order() -> Result<()> {
+        for (modifier, expected) in [
+            (Modifier::Major(5), vec![67, 72, 76]),
+            (Modifier::Dominant(9), vec![67, 70, 72, 74, 76]),
+        ] {
+            let mut chord = Chord::new(4, 2, Key::Absolute(Pitch::C));
+            chord.modify(&Modifier::Major(5))?;
+            chord.modify(&modifier)?;
+            let notes = EXPORTER.note_numbers(&chord)?;
+            assert_eq!(notes.iter().map(|n| n.get()).collect::<Vec<_>>(), expected);
+        }
+        // raised tones, a doubled root and an on-chord still come out ascending
+        let mut chord = Chord::new(4, 3, Key::Absolute(Pitch::C));
+        chord.modify(&Modifier::Major(5))?;
+        chord.modify(&Modifier::Dominant(9))?;
+        chord.on = Some(Key::Absolute(Pitch::E));
+        let exporter = MidiExporter {
+            doubling: Some(Doubling::Root),
+            ..EXPORTER
+        };
+        let notes = exporter.note_numbers(&chord)?;
+        assert_eq!(
+            notes.iter().map(|n| n.get()).collect::<Vec<_>>(),
+            vec![52, 70, 72, 74, 76, 79, 84]
+        );
+        Ok(())
+    }
 
-    let mut skip = 0;
-    for note in notes {
-        let dur = note.duration * UNIT;
-        let Some(chord) = &note.chord else {
-            skip = dur;
-            continue;
+    #[test]
+    fn test_key() -> Result<()> {
+        let exporter = MidiExporter {
+            key: Some(Pitch::C),
+            ..EXPORTER
         };
-        write_notes(&mut track, ch, chord, dur, &mut skip);
+        assert_eq!(
+            export(&exporter, "I IV V | bVII\n")?,
+            export(&exporter, "C F G | Bb\n")?
+        );
+        assert!(export(&EXPORTER, "I IV V\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_subdivision() -> Result<()> {
+        let score = interpret("{3} C G Am | C\n")?;
+        let durations = score.notes.iter().map(|n| n.duration).collect::<Vec<_>>();
+        assert_eq!(durations, vec![1365, 1365, 1366, MEASURE_LENGTH * UNIT]);
+        assert!(interpret("{2} C G Am\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder() -> Result<()> {
+        let default = MidiExporter::default();
+        assert_eq!(
+            (default.bpm, default.middle_c_octave, default.key),
+            (120.0, 4, None)
+        );
+        let exporter = MidiExporter::builder()
+            .bpm(90.0)
+            .key(Pitch::D)
+            .instrument(GeneralMidi::AcousticGrandPiano)
+            .build();
+        assert_eq!(exporter.bpm, 90.0);
+        assert_eq!(exporter.key, Some(Pitch::D));
+        assert_eq!(
+            exporter.middle_c_octave,
+            MidiExporter::default().middle_c_octave
+        );
+        let programs = export(&exporter, "I\n")?
+            .into_iter()
+            .filter_map(|(_, e)| match e {
+                Event::Midi(Message::ProgramChange(p)) => Some(p.program().get()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(programs, vec![GeneralMidi::AcousticGrandPiano as u8]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_doubling() -> Result<()> {
+        let mut chord = Chord::new(4, 0, Key::Absolute(Pitch::C));
+        chord.modify(&Modifier::Major(5))?;
+        for (doubling, expected) in [
+            (Doubling::Root, vec![60, 64, 67, 72]),
+            (Doubling::Fifth, vec![60, 64, 67, 79]),
+        ] {
+            let exporter = MidiExporter::builder().doubling(doubling).build();
+            let notes = exporter.note_numbers(&chord)?;
+            assert_eq!(notes.iter().map(|n| n.get()).collect::<Vec<_>>(), expected);
+        }
+
+        // no room an octave above
+        let exporter = MidiExporter::builder().doubling(Doubling::Root).build();
+        let mut chord = Chord::new(9, 0, Key::Absolute(Pitch::C));
+        chord.modify(&Modifier::Major(5))?;
+        assert_eq!(exporter.note_numbers(&chord)?.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_voicing_seed() -> Result<()> {
+        let code = "C G | Am F | Dm7 G7 | C\n";
+        let seeded = |seed| export(&MidiExporter::builder().voicing_seed(seed).build(), code);
+        assert_eq!(seeded(42)?, seeded(42)?);
+        assert_ne!(seeded(42)?, seeded(7)?);
+        assert_ne!(seeded(42)?, export(&MidiExporter::default(), code)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bass_only() -> Result<()> {
+        let exporter = MidiExporter {
+            bass_only: true,
+            ..EXPORTER
+        };
+        let ast = RechordImporter::default().import("C G7 | Am9 F/A\n")?;
+        let mut score = Score::new(&exporter);
+        score.interpret(ast)?;
+        let notes = score
+            .notes
+            .into_iter()
+            .flat_map(|n| n.chord)
+            .collect::<Vec<_>>();
+        assert_eq!(notes.len(), 4);
+        for (notes, (_, chord)) in notes.iter().zip(score.chords.iter()) {
+            assert_eq!(notes.len(), 1);
+            let bass = match &chord.on {
+                Some(Key::Absolute(on)) => midi_note(chord.octave - 1, *on as u8),
+                _ => chord.root_note_number()? - 12,
+            };
+            assert_eq!(notes[0].get(), bass);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_node_length() -> Result<()> {
+        let exporter = MidiExporter::builder().node_length(4).build();
+        let ast = RechordImporter::default().import("C G Am F Dm G7 | C\n")?;
+        let mut score = Score::new(&exporter);
+        score.interpret(ast)?;
+        let durations = score.notes.iter().map(|n| n.duration).collect::<Vec<_>>();
+        assert_eq!(durations, vec![4 * UNIT; 7]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tuplet() -> Result<()> {
+        let score = interpret("C G (Am F E)3\n")?;
+        let durations = score.notes.iter().map(|n| n.duration).collect::<Vec<_>>();
+        assert_eq!(durations.len(), 5);
+        assert_eq!(durations[0], durations[1]);
+        assert_eq!(durations[2..].iter().sum::<u32>(), 2 * durations[0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pcset_sequence() -> Result<()> {
+        let ast = RechordImporter::default().import("C | _ G7 & Am\n")?;
+        let bar = MEASURE_LENGTH * UNIT;
+        assert_eq!(
+            to_pcset_sequence(ast, Pitch::C)?,
+            vec![
+                (0b000010010001, bar),
+                (0, bar / 2),
+                (0b100010100100, bar / 2)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_note_events() -> Result<()> {
+        let ast = RechordImporter::default().import("C G\n")?;
+        let events = to_note_events(ast, Pitch::C, 120.0)?;
+        let json = serde_json::to_value(&events)?;
+        assert_eq!(
+            json,
+            serde_json::json!([
+                { "voice": 0, "start": 0.0, "duration": 1.0, "notes": [60, 64, 67], "velocity": 72 },
+                { "voice": 0, "start": 1.0, "duration": 1.0, "notes": [59, 62, 67], "velocity": 72 },
+            ])
+        );
+        // `@tempo`s change the pace from their measure on
+        let ast = RechordImporter::default().import("@tempo q=60\nC\n@tempo q=120\nG\n")?;
+        let events = to_note_events(ast, Pitch::C, 120.0)?;
+        let times = events
+            .iter()
+            .map(|e| (e.start, e.duration))
+            .collect::<Vec<_>>();
+        assert_eq!(times, vec![(0.0, 4.0), (4.0, 2.0)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_push() -> Result<()> {
+        let onsets = |code| -> Result<Vec<u32>> {
+            Ok(export(&EXPORTER, code)?
+                .into_iter()
+                .filter(|(_, e)| matches!(e, Event::Midi(Message::NoteOn(_))))
+                .map(|(tick, _)| tick)
+                .collect())
+        };
+        let nominal = onsets("C G\n")?;
+        let pushed = onsets("C >G\n")?;
+        assert_eq!(nominal.last(), Some(&(8 * UNIT)));
+        assert_eq!(pushed.last(), Some(&(6 * UNIT)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rest_gap() -> Result<()> {
+        let events = export(&EXPORTER, "C _ G\n")?
+            .into_iter()
+            .filter_map(|(tick, e)| match e {
+                Event::Midi(Message::NoteOn(_)) => Some((tick, true)),
+                Event::Midi(Message::NoteOff(_)) => Some((tick, false)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let quarter = 4 * UNIT;
+        // C stops at its own end, nothing sounds through the rest
+        let c_off = events
+            .iter()
+            .filter(|(tick, on)| !on && *tick == quarter)
+            .count();
+        assert_eq!(c_off, 3);
+        assert!(events
+            .iter()
+            .all(|(tick, _)| *tick <= quarter || *tick >= 2 * quarter));
+        let g_on = events
+            .iter()
+            .position(|(tick, on)| *on && *tick == 2 * quarter);
+        let last_c_off = events
+            .iter()
+            .rposition(|(tick, on)| !on && *tick == quarter);
+        assert!(last_c_off < g_on);
+        Ok(())
+    }
+
+    #[test]
+    fn test_let_ring() -> Result<()> {
+        let offs = |code| -> Result<Vec<u32>> {
+            Ok(export(&EXPORTER, code)?
+                .into_iter()
+                .filter(|(_, e)| matches!(e, Event::Midi(Message::NoteOff(_))))
+                .map(|(tick, _)| tick)
+                .collect())
+        };
+        let quarter = 4 * UNIT;
+        // C rings until G
+        assert_eq!(offs("C* _ _ G\n")?[..3], [3 * quarter; 3]);
+        assert_eq!(offs("C _ _ G\n")?[..3], [quarter; 3]);
+        // G after the ring chord is cut off by rests again
+        assert_eq!(offs("C* G _ _\n")?[3..], [2 * quarter; 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pedal() -> Result<()> {
+        let exporter = MidiExporter {
+            pedal: true,
+            ..EXPORTER
+        };
+        let pedal = export(&exporter, "C G | _ F\n")?
+            .into_iter()
+            .filter_map(|(tick, e)| match e {
+                Event::Midi(Message::Control(cc)) => {
+                    assert_eq!(cc.control() as u8, 64);
+                    Some((tick, cc.value().get()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let half = 8 * UNIT;
+        assert_eq!(
+            pedal,
+            vec![
+                (0, 127),
+                (half, 0),
+                (half, 127),
+                // released through the rest
+                (2 * half, 0),
+                (3 * half, 127),
+                (4 * half, 0),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_chord_timeline() -> Result<()> {
+        let ast = RechordImporter::default().import("C = G _ | <C>F % |\n")?;
+        let timeline = EXPORTER
+            .chord_timeline(ast)?
+            .into_iter()
+            .map(|(onset, duration, chord)| (onset, duration, chord.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            timeline,
+            vec![
+                (0.0, 2.0, "C".to_string()),
+                (2.0, 1.0, "G".to_string()),
+                (4.0, 2.0, "F".to_string()),
+                (6.0, 2.0, "F".to_string()),
+            ]
+        );
+        Ok(())
     }
 
-    mfile.push_track(track)?;
-    mfile.write(f)?;
-    Ok(())
+    #[test]
+    fn test_dynamics() -> Result<()> {
+        let score = interpret("@dynamics cresc 60 100 2\nC C | C C |\nC | C\n")?;
+        let velocities = score
+            .notes
+            .iter()
+            .map(|n| n.velocity.get())
+            .collect::<Vec<_>>();
+        assert_eq!(velocities, vec![60, 70, 80, 90, 100, 100]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_velocity_reset() -> Result<()> {
+        let code = "@vel 100\nC | =\n[B]\n@vel reset\nG\n";
+        let ast = RechordImporter::default().import(code)?;
+        assert!(ast.to_string().contains("@vel reset"));
+        let velocities = interpret(code)?
+            .notes
+            .iter()
+            .map(|n| n.velocity.get())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            velocities,
+            vec![100, midi_file::core::Velocity::default().get()]
+        );
+        assert!(RechordImporter::default().import("@vel 128\nC\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_staccato() -> Result<()> {
+        let note_offs = export(&EXPORTER, "C. G | C.\n")?
+            .into_iter()
+            .filter_map(|(tick, e)| match e {
+                Event::Midi(Message::NoteOff(n)) => Some((tick, n.note_number().get())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let half = MEASURE_LENGTH * UNIT / 2;
+        assert_eq!(
+            note_offs,
+            vec![
+                (half / 2, 60),
+                (half / 2, 64),
+                (half / 2, 67),
+                (2 * half, 59),
+                (2 * half, 62),
+                (2 * half, 67),
+                (3 * half, 60),
+                (3 * half, 64),
+                (3 * half, 67)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_voices() -> Result<()> {
+        let note_ons = export(&EXPORTER, "C & Eomit3omit5 | G\n")?
+            .into_iter()
+            .filter_map(|(tick, e)| match e {
+                Event::Midi(Message::NoteOn(n)) => Some((tick, n.channel().get())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let bar = MEASURE_LENGTH * UNIT;
+        assert_eq!(
+            note_ons,
+            vec![(0, 0), (0, 0), (0, 0), (0, 1), (bar, 0), (bar, 0), (bar, 0)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_grace() -> Result<()> {
+        let score = interpret("<C>G\n")?;
+        let mut c = ChordNode::absolute(Pitch::C);
+        c.modifiers.insert(Modifier::Major(5));
+        let c = EXPORTER.note_numbers(&c.to_chord()?)?;
+        assert_eq!(score.notes.len(), 2);
+        assert_eq!(score.notes[0].chord, Some(c));
+        assert_eq!(score.notes[0].duration, GRACE_TICKS);
+        assert_eq!(score.notes[1].duration, MEASURE_LENGTH * UNIT - GRACE_TICKS);
+
+        // slots shorter than a grace split between the grace and the chord
+        let score = interpret("{255} <C>G\n")?;
+        let slot = MEASURE_LENGTH * UNIT / 255;
+        assert_eq!(score.notes[0].duration, slot / 2);
+        assert_eq!(score.notes[1].duration, slot - slot / 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_alternates() -> Result<()> {
+        let ast = RechordImporter::default().import("C(Am) | G\n")?;
+        let mut written = vec![];
+        EXPORTER.export_debug_notes(&mut written, ast.clone())?;
+        assert_eq!(String::from_utf8(written)?, "0: C4 E4 G4\n4096: B3 D4 G4\n");
+        let exporter = MidiExporter {
+            alternates: true,
+            ..EXPORTER
+        };
+        let mut alternates = vec![];
+        exporter.export_debug_notes(&mut alternates, ast)?;
+        assert_eq!(
+            String::from_utf8(alternates)?,
+            "0: C4 E4 A4\n4096: B3 D4 G4\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_strum() -> Result<()> {
+        let note_ons = |exporter: &MidiExporter| -> Result<Vec<(u32, u8)>> {
+            Ok(export(exporter, "C | C/G\n")?
+                .into_iter()
+                .filter_map(|(tick, e)| match e {
+                    Event::Midi(Message::NoteOn(n)) => Some((tick, n.note_number().get())),
+                    _ => None,
+                })
+                .collect())
+        };
+        let m = MEASURE_LENGTH * UNIT;
+        let exporter = MidiExporter {
+            strum_ticks: 10,
+            ..EXPORTER
+        };
+        assert_eq!(
+            note_ons(&exporter)?,
+            vec![
+                (0, 60),
+                (10, 64),
+                (20, 67),
+                (m, 55),
+                (m + 10, 60),
+                (m + 20, 64),
+                (m + 30, 67)
+            ]
+        );
+        // up strokes start from the top
+        let exporter = MidiExporter {
+            alternate_strum: true,
+            ..exporter
+        };
+        assert_eq!(
+            note_ons(&exporter)?,
+            vec![
+                (0, 60),
+                (10, 64),
+                (20, 67),
+                (m, 67),
+                (m + 10, 64),
+                (m + 20, 60),
+                (m + 30, 55)
+            ]
+        );
+        // without strum_ticks only the order alternates
+        let exporter = MidiExporter {
+            alternate_strum: true,
+            ..EXPORTER
+        };
+        let pitches = note_ons(&exporter)?
+            .into_iter()
+            .map(|(_, n)| n)
+            .collect::<Vec<_>>();
+        assert_eq!(pitches, vec![60, 64, 67, 67, 64, 60, 55]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_swing() -> Result<()> {
+        let onsets = |swing_subdivision, swing, sixteenths| -> Result<Vec<u32>> {
+            let exporter = MidiExporter {
+                swing,
+                swing_subdivision,
+                node_length: Some(sixteenths),
+                ..EXPORTER
+            };
+            let mut onsets = export(&exporter, "C G C G\n")?
+                .into_iter()
+                .filter(|(_, e)| matches!(e, Event::Midi(Message::NoteOn(_))))
+                .map(|(tick, _)| tick)
+                .collect::<Vec<_>>();
+            onsets.dedup();
+            Ok(onsets)
+        };
+        let (eighth, sixteenth) = (NoteValue::Eighth, NoteValue::Sixteenth);
+        // eighth swing delays every other eighth to the last triplet of its beat
+        assert_eq!(onsets(eighth, 2.0 / 3.0, 2)?, vec![0, 683, 1024, 1707]);
+        assert_eq!(onsets(sixteenth, 2.0 / 3.0, 2)?, vec![0, 512, 1024, 1536]);
+        assert_eq!(onsets(sixteenth, 2.0 / 3.0, 1)?, vec![0, 341, 512, 853]);
+        assert_eq!(onsets(sixteenth, 0.5, 1)?, vec![0, 256, 512, 768]);
+        for swing in [0.0, 1.0, 1.5, -0.5, f32::NAN] {
+            assert!(onsets(eighth, swing, 2).is_err(), "{}", swing);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_fit_range() -> Result<()> {
+        let ast = RechordImporter::default().import("@octave 2\nC | G7\n")?;
+        let (_, max) = EXPORTER.note_range(ast.clone())?.unwrap();
+        assert!(max > 84);
+
+        let fitted = EXPORTER.fit_range(ast, 48, 84)?;
+        let (min, max) = EXPORTER.note_range(fitted.clone())?.unwrap();
+        assert!(48 <= min && max <= 84);
+        let Ast::Score(score) = &fitted else { panic!() };
+        assert_eq!(*score[0], Ast::Directive(Directive::Octave(-1)));
+
+        let ast = RechordImporter::default().import("C\n")?;
+        assert_eq!(EXPORTER.fit_range(ast.clone(), 0, 127)?, ast);
+        assert!(EXPORTER.fit_range(ast, 60, 62).is_err());
+        // degree charts are placed in the exporter's key
+        let ast = RechordImporter::default().import("I | V7\n")?;
+        let exporter = MidiExporter {
+            key: Some(Pitch::C),
+            ..EXPORTER
+        };
+        assert_eq!(exporter.fit_range(ast.clone(), 0, 127)?, ast);
+        assert!(EXPORTER.fit_range(ast, 0, 127).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_middle_c_octave() -> Result<()> {
+        let mut chord = Chord::new(4, 0, Key::Absolute(Pitch::C));
+        chord.modify(&Modifier::Major(5))?;
+        for (middle_c_octave, expected) in [(3, 72), (4, 60), (5, 48)] {
+            let exporter = MidiExporter {
+                middle_c_octave,
+                ..EXPORTER
+            };
+            let notes = exporter.note_numbers(&chord)?;
+            assert_eq!(
+                notes.iter().map(|n| n.get()).collect::<Vec<_>>(),
+                vec![expected, expected + 4, expected + 7]
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_midi_note() -> Result<()> {
+        let mut chord = Chord::new(5, 0, Key::Absolute(Pitch::C));
+        chord.modify(&Modifier::Major(5))?;
+        let c5 = midi_note(5, 0);
+        assert_eq!(c5, 72);
+        assert_eq!(chord.root_note_number()?, c5);
+        assert_eq!(chord.root_pitch()?, c5);
+        assert_eq!(EXPORTER.note_numbers(&chord)?[0].get(), c5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tempo() -> Result<()> {
+        for (bpm, expected) in [(300.0, 200_000), (92.5, 648_649)] {
+            let exporter = MidiExporter { bpm, ..EXPORTER };
+            let tempo = export(&exporter, "C\n")?
+                .into_iter()
+                .find_map(|(_, e)| match e {
+                    Event::Meta(MetaEvent::SetTempo(tempo)) => Some(tempo.get()),
+                    _ => None,
+                });
+            assert_eq!(tempo, Some(expected));
+        }
+        let exporter = MidiExporter {
+            bpm: 0.0,
+            ..EXPORTER
+        };
+        assert!(export(&exporter, "C\n").is_err());
+        // `@tempo` replaces the exporter's tempo and changes it later on
+        let tempos = export(&EXPORTER, "@tempo h=60\nC\n@tempo q=90\nG\n")?
+            .into_iter()
+            .filter_map(|(tick, e)| match e {
+                Event::Meta(MetaEvent::SetTempo(tempo)) => Some((tick, tempo.get())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(tempos, vec![(0, 500_000), (16 * UNIT, 666_667)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_marker() -> Result<()> {
+        let events = export(&EXPORTER, "[Verse]\nC | G\n[Chorus]\nAm | F\n")?;
+        let markers = events
+            .into_iter()
+            .filter_map(|(tick, e)| match e {
+                Event::Meta(MetaEvent::Marker(label)) => Some((tick, label.to_string())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            markers,
+            vec![
+                (0, "Verse".to_string()),
+                (2 * MEASURE_LENGTH * UNIT, "Chorus".to_string())
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_release_velocity() -> Result<()> {
+        let exporter = MidiExporter {
+            release_velocity: 100,
+            ..EXPORTER
+        };
+        let velocities = export(&exporter, "C\n")?
+            .into_iter()
+            .filter_map(|(_, e)| match e {
+                Event::Midi(Message::NoteOff(n)) => Some(n.velocity().get()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(velocities, vec![100, 100, 100]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_debug_notes() -> Result<()> {
+        let ast = RechordImporter::default().import("C | G7 =\n")?;
+        let mut buf = vec![];
+        EXPORTER.export_debug_notes(&mut buf, ast)?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            format!("0: C4 E4 G4\n{}: B3 D4 F4 G4\n", MEASURE_LENGTH * UNIT)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_variations() -> Result<()> {
+        let exporter = MidiExporter {
+            variations: vec![VoicingStyle::Block, VoicingStyle::Rootless],
+            ..EXPORTER
+        };
+        let ast = RechordImporter::default().import("Cmaj7 | Am7\n")?;
+        let mut buf = vec![];
+        exporter.export_debug_notes(&mut buf, ast)?;
+        let bar = MEASURE_LENGTH * UNIT;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            format!(
+                "0: C4 E4 G4 B4\n{}: C4 E4 G4 A4\n{}: E4 G4 B4\n{}: E4 G4 C5\n",
+                bar,
+                2 * bar,
+                3 * bar
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_voices() -> Result<()> {
+        let exporter = MidiExporter {
+            split_voices: true,
+            ..EXPORTER
+        };
+        let mut onsets: Vec<(u32, Vec<(u8, u8)>)> = vec![];
+        for (tick, e) in export(&exporter, "C | G7 | Am/E | F(9)\n")? {
+            let Event::Midi(Message::NoteOn(n)) = e else {
+                continue;
+            };
+            let note = (n.channel().get(), n.note_number().get());
+            match onsets.last_mut() {
+                Some((t, notes)) if *t == tick => notes.push(note),
+                _ => onsets.push((tick, vec![note])),
+            }
+        }
+        assert_eq!(onsets.len(), 4);
+        for (tick, notes) in onsets {
+            let lowest = notes.iter().map(|(_, n)| *n).min();
+            let bass = notes.iter().filter(|(ch, _)| *ch == 3).collect::<Vec<_>>();
+            assert_eq!(bass.len(), 1, "{}", tick);
+            assert_eq!(Some(bass[0].1), lowest, "{}", tick);
+            let highest = notes.iter().map(|(_, n)| *n).max();
+            let soprano = notes.iter().find(|(ch, _)| *ch == 0).map(|(_, n)| *n);
+            assert_eq!(soprano, highest, "{}", tick);
+        }
+        Ok(())
+    }
 }
@@ -1,17 +1,244 @@
-use crate::model::ast::Ast;
+use crate::model::{ast::Ast, pitch::Pitch};
+use midi_file::core::GeneralMidi;
 use std::io::Write;
 
+mod chord_track;
+mod degree;
+mod improv;
+mod layout;
 mod midi;
+mod notation;
 mod rechord;
+mod sonic_pi;
+mod transpose;
+
+pub use layout::{ChartLayout, MeasureLayout, SymbolLayout, System};
+pub use midi::{to_note_events, to_pcset_sequence, NoteEvent};
+pub use notation::{ticks_to_notated, NotatedDuration, NoteValue};
 
 pub trait Exporter {
     fn export(&self, f: &mut impl Write, ast: Ast) -> anyhow::Result<()>;
 }
 
+#[derive(Debug, Default)]
+pub struct RechordExporter {
+    /// wrap measures into lines of this many measures, `0` keeps the original line breaks
+    pub measures_per_line: usize,
+    /// pad measures so barlines line up in columns
+    pub align: bool,
+}
+
+#[derive(Debug)]
+pub struct DegreeExporter {
+    pub key: Pitch,
+    /// write inversions as figured bass (`I6`, `V6/5`) instead of on-chords
+    pub figured_bass: bool,
+}
+
+/// each chord with its suggested mode and the mode's notes, one per line
+#[derive(Debug)]
+pub struct ImprovExporter;
+
+/// chords with their bar/beat position as JSON, for DAW chord tracks
+#[derive(Debug)]
+pub struct ChordTrackJsonExporter;
+
+/// `ChartLayout` as JSON, for renderers laying the chart out on pages
+#[derive(Debug)]
+pub struct LayoutExporter {
+    pub bars_per_system: usize,
+}
+
+/// the chart in all 12 keys from `key` up, each headed by a `# Key:` comment
+#[derive(Debug)]
+pub struct TransposeAllExporter {
+    /// key the chart is written in
+    pub key: Pitch,
+    pub chart: RechordExporter,
+}
+
+/// `play_chord [:c4, :e4, :g4]` and `sleep` in beats for each chord, for Sonic Pi
 #[derive(Debug)]
-pub struct RechordExporter;
+pub struct SonicPiExporter {
+    /// key to render degree chords in
+    pub key: Pitch,
+}
 
 #[derive(Debug)]
 pub struct MidiExporter {
-    pub bpm: u8,
+    /// quarters per minute, fractional and above 255 allowed
+    pub bpm: f32,
+    /// octave whose C is note number 60 (`4` for C4=60)
+    pub middle_c_octave: u8,
+    /// note-off velocity (`72` is the MIDI file default)
+    pub release_velocity: u8,
+    /// key to render degree chords in, `None` rejects them
+    pub key: Option<Pitch>,
+    pub instrument: GeneralMidi,
+    /// chord tone to add again an octave above
+    pub doubling: Option<Doubling>,
+    /// seed to pick a random inversion and tension for each chord, `None` renders chords as written
+    pub voicing_seed: Option<u64>,
+    /// renders only the bass, the on-chord or the root an octave below the chord
+    pub bass_only: bool,
+    /// presses the sustain pedal (CC64) through each chord and lifts it at chord changes and rests
+    pub pedal: bool,
+    /// sixteenths each node lasts regardless of its measure, barlines only group nodes for reading.
+    /// `None` divides each measure among its nodes
+    pub node_length: Option<u32>,
+    /// renders the chart once per style back to back, empty renders it once as written
+    pub variations: Vec<VoicingStyle>,
+    /// spreads chord tones over soprano, alto, tenor and bass channels of each voice
+    pub split_voices: bool,
+    /// plays the alternate of `C(Am)` instead of the chord
+    pub alternates: bool,
+    /// ticks between the note-ons of a chord from its lowest note up, `0` plays them together
+    pub strum_ticks: u32,
+    /// strums every other chord from its highest note down, like up strokes
+    pub alternate_strum: bool,
+    /// where the off-beat of each pair of `swing_subdivision`s lands, as a fraction of the pair.
+    /// `0.5` plays straight, `2/3` a triplet feel
+    pub swing: f32,
+    /// note value whose off-beats are swung, `Eighth` or `Sixteenth` by genre
+    pub swing_subdivision: NoteValue,
+}
+
+/// how the chords of one pass of `variations` are voiced
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VoicingStyle {
+    /// chord tones as written
+    Block,
+    /// leaves out the root of chords with four or more tones
+    Rootless,
+    /// `Chord::drop2`
+    Drop2,
+    /// `Chord::drop3`
+    Drop3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Doubling {
+    Root,
+    Fifth,
+}
+
+impl Default for MidiExporter {
+    fn default() -> Self {
+        MidiExporter {
+            bpm: 120.0,
+            middle_c_octave: 4,
+            release_velocity: 72,
+            key: None,
+            instrument: GeneralMidi::SynthVoice,
+            doubling: None,
+            voicing_seed: None,
+            bass_only: false,
+            pedal: false,
+            node_length: None,
+            variations: vec![],
+            split_voices: false,
+            alternates: false,
+            strum_ticks: 0,
+            alternate_strum: false,
+            swing: 0.5,
+            swing_subdivision: NoteValue::Eighth,
+        }
+    }
+}
+
+impl MidiExporter {
+    pub fn builder() -> MidiExporterBuilder {
+        MidiExporterBuilder(MidiExporter::default())
+    }
+}
+
+/// `MidiExporter::builder().bpm(90.0).key(Pitch::C).build()`
+#[derive(Debug, Default)]
+pub struct MidiExporterBuilder(MidiExporter);
+
+impl MidiExporterBuilder {
+    pub fn bpm(mut self, bpm: f32) -> Self {
+        self.0.bpm = bpm;
+        self
+    }
+
+    pub fn middle_c_octave(mut self, middle_c_octave: u8) -> Self {
+        self.0.middle_c_octave = middle_c_octave;
+        self
+    }
+
+    pub fn release_velocity(mut self, release_velocity: u8) -> Self {
+        self.0.release_velocity = release_velocity;
+        self
+    }
+
+    pub fn key(mut self, key: Pitch) -> Self {
+        self.0.key = Some(key);
+        self
+    }
+
+    pub fn instrument(mut self, instrument: GeneralMidi) -> Self {
+        self.0.instrument = instrument;
+        self
+    }
+
+    pub fn doubling(mut self, doubling: Doubling) -> Self {
+        self.0.doubling = Some(doubling);
+        self
+    }
+
+    pub fn voicing_seed(mut self, seed: u64) -> Self {
+        self.0.voicing_seed = Some(seed);
+        self
+    }
+
+    pub fn bass_only(mut self, bass_only: bool) -> Self {
+        self.0.bass_only = bass_only;
+        self
+    }
+
+    pub fn pedal(mut self, pedal: bool) -> Self {
+        self.0.pedal = pedal;
+        self
+    }
+
+    pub fn node_length(mut self, sixteenths: u32) -> Self {
+        self.0.node_length = Some(sixteenths);
+        self
+    }
+
+    pub fn variations(mut self, variations: Vec<VoicingStyle>) -> Self {
+        self.0.variations = variations;
+        self
+    }
+
+    pub fn split_voices(mut self, split_voices: bool) -> Self {
+        self.0.split_voices = split_voices;
+        self
+    }
+
+    pub fn alternates(mut self, alternates: bool) -> Self {
+        self.0.alternates = alternates;
+        self
+    }
+
+    pub fn strum_ticks(mut self, strum_ticks: u32) -> Self {
+        self.0.strum_ticks = strum_ticks;
+        self
+    }
+
+    pub fn alternate_strum(mut self, alternate_strum: bool) -> Self {
+        self.0.alternate_strum = alternate_strum;
+        self
+    }
+
+    pub fn swing(mut self, swing: f32, subdivision: NoteValue) -> Self {
+        self.0.swing = swing;
+        self.0.swing_subdivision = subdivision;
+        self
+    }
+
+    pub fn build(self) -> MidiExporter {
+        self.0
+    }
 }
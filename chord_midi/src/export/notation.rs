@@ -0,0 +1,108 @@
+use anyhow::{anyhow, Result};
+
+/// note values down to a sixteenth, the shortest a chart can write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteValue {
+    Whole,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+}
+
+impl NoteValue {
+    const ALL: [NoteValue; 5] = [
+        NoteValue::Whole,
+        NoteValue::Half,
+        NoteValue::Quarter,
+        NoteValue::Eighth,
+        NoteValue::Sixteenth,
+    ];
+
+    /// length in sixteenths
+    pub(crate) fn sixteenths(&self) -> u32 {
+        match self {
+            NoteValue::Whole => 16,
+            NoteValue::Half => 8,
+            NoteValue::Quarter => 4,
+            NoteValue::Eighth => 2,
+            NoteValue::Sixteenth => 1,
+        }
+    }
+}
+
+/// a note value to print, tied to the next one when a duration takes several
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotatedDuration {
+    pub value: NoteValue,
+    pub dotted: bool,
+}
+
+/// splits `ticks` into tied note values, longest first, for notation exporters.
+/// `ppq` is ticks per quarter and the duration must be whole sixteenths
+pub fn ticks_to_notated(ticks: u32, ppq: u32) -> Result<Vec<NotatedDuration>> {
+    if ppq == 0 || !ppq.is_multiple_of(4) || !ticks.is_multiple_of(ppq / 4) {
+        return Err(anyhow!(
+            "{} ticks at {} ppq is not in sixteenths",
+            ticks,
+            ppq
+        ));
+    }
+    let mut rest = ticks / (ppq / 4);
+    let mut durations = vec![];
+    while rest > 0 {
+        // a dotted sixteenth would need a thirty-second
+        let (value, dotted) = NoteValue::ALL
+            .iter()
+            .flat_map(|v| [(*v, true), (*v, false)])
+            .filter(|(v, dotted)| !(*dotted && *v == NoteValue::Sixteenth))
+            .find(|(v, dotted)| length(v, *dotted) <= rest)
+            .unwrap_or((NoteValue::Sixteenth, false));
+        rest -= length(&value, dotted);
+        durations.push(NotatedDuration { value, dotted });
+    }
+    Ok(durations)
+}
+
+fn length(value: &NoteValue, dotted: bool) -> u32 {
+    if dotted {
+        value.sixteenths() * 3 / 2
+    } else {
+        value.sixteenths()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ticks_to_notated, NotatedDuration, NoteValue};
+    use crate::export::midi::UNIT;
+    use anyhow::Result;
+
+    #[test]
+    fn test_ticks_to_notated() -> Result<()> {
+        let ppq = 4 * UNIT;
+        let note = |value, dotted| NotatedDuration { value, dotted };
+        // 3/8 of a bar
+        assert_eq!(
+            ticks_to_notated(6 * UNIT, ppq)?,
+            vec![note(NoteValue::Quarter, true)]
+        );
+        assert_eq!(
+            ticks_to_notated(5 * UNIT, ppq)?,
+            vec![
+                note(NoteValue::Quarter, false),
+                note(NoteValue::Sixteenth, false)
+            ]
+        );
+        assert_eq!(
+            ticks_to_notated(22 * UNIT, ppq)?,
+            vec![
+                note(NoteValue::Whole, false),
+                note(NoteValue::Quarter, true)
+            ]
+        );
+        assert!(ticks_to_notated(0, ppq)?.is_empty());
+        assert!(ticks_to_notated(UNIT / 2, ppq).is_err());
+        Ok(())
+    }
+}
@@ -1,33 +1,119 @@
-use super::{Exporter, RechordExporter};
-use crate::model::ast::{Ast, ChordNode, Node};
+use super::{
+    degree::{figure, is_seventh},
+    Exporter, RechordExporter,
+};
+use crate::model::{
+    ast::{Ast, ChordNode, Directive, Node},
+    modifier::Modifier,
+};
 use std::{fmt::Display, io::Write};
 
 impl Exporter for RechordExporter {
     fn export(&self, f: &mut impl Write, ast: Ast) -> anyhow::Result<()> {
-        writeln!(f, "{}", ast)?;
+        if self.measures_per_line == 0 {
+            writeln!(f, "{}", ast)?;
+            return Ok(());
+        }
+        for line in self.layout(ast) {
+            writeln!(f, "{}", line)?;
+        }
         Ok(())
     }
 }
 
+enum Line {
+    Text(String),
+    Measures(Vec<String>),
+}
+
+impl RechordExporter {
+    /// returns lines of `measures_per_line` measures, comments and markers on their own lines
+    fn layout(&self, ast: Ast) -> Vec<String> {
+        let items = match ast {
+            Ast::Score(score) => score.into_iter().map(|ast| *ast).collect(),
+            ast => vec![ast],
+        };
+        let mut lines = vec![];
+        let mut measures = vec![];
+        for item in items {
+            match item {
+                Ast::Measure(nodes, _) => {
+                    let nodes = nodes.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+                    measures.push(nodes.join(" "));
+                    if measures.len() == self.measures_per_line {
+                        lines.push(Line::Measures(std::mem::take(&mut measures)));
+                    }
+                }
+                other => {
+                    if !measures.is_empty() {
+                        lines.push(Line::Measures(std::mem::take(&mut measures)));
+                    }
+                    lines.push(Line::Text(other.to_string().trim_end().to_string()));
+                }
+            }
+        }
+        if !measures.is_empty() {
+            lines.push(Line::Measures(measures));
+        }
+
+        let mut widths = vec![0; self.measures_per_line];
+        if self.align {
+            for line in &lines {
+                if let Line::Measures(measures) = line {
+                    for (i, m) in measures.iter().enumerate() {
+                        widths[i] = widths[i].max(m.chars().count());
+                    }
+                }
+            }
+        }
+        lines
+            .into_iter()
+            .map(|line| match line {
+                Line::Text(text) => text,
+                Line::Measures(measures) => measures
+                    .iter()
+                    .enumerate()
+                    .map(|(i, m)| format!("{:<width$} |", m, width = widths[i]))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            })
+            .collect()
+    }
+}
+
 impl Display for Ast {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Ast::Score(nodes) => {
                 for node in nodes {
-                    write!(f, "{}", node)?;
+                    if f.alternate() {
+                        write!(f, "{:#}", node)?;
+                    } else {
+                        write!(f, "{}", node)?;
+                    }
                 }
                 Ok(())
             }
             Ast::Comment(comment) => {
                 writeln!(f, "# {}", comment)
             }
+            Ast::Marker(label) => {
+                writeln!(f, "[{}]", label)
+            }
+            Ast::Directive(directive) => {
+                writeln!(f, "{}", directive)
+            }
             Ast::Measure(nodes, br) => {
                 write!(
                     f,
                     "{}",
                     nodes
                         .iter()
-                        .map(|n| n.to_string())
+                        .map(|n| if f.alternate() {
+                            format!("{:#}", n)
+                        } else {
+                            n.to_string()
+                        })
                         .collect::<Vec<_>>()
                         .join(" ")
                 )?;
@@ -41,30 +127,162 @@ impl Display for Ast {
     }
 }
 
+impl Display for Directive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Directive::Dynamics { from, to, measures } => {
+                let kind = if to < from { "dim" } else { "cresc" };
+                write!(f, "@dynamics {} {} {} {}", kind, from, to, measures)
+            }
+            Directive::Bars(bars) => write!(f, "@bars {}", bars),
+            Directive::Octave(octave) => write!(f, "@octave {}", octave),
+            Directive::Velocity(Some(velocity)) => write!(f, "@vel {}", velocity),
+            Directive::Velocity(None) => write!(f, "@vel reset"),
+            Directive::Tempo { beat, per_minute } => {
+                let note = match beat {
+                    16 | 24 => "w",
+                    8 | 12 => "h",
+                    4 | 6 => "q",
+                    _ => "e",
+                };
+                let dot = if beat % 3 == 0 { "." } else { "" };
+                write!(f, "@tempo {}{}={}", note, dot, per_minute)
+            }
+        }
+    }
+}
+
 impl Display for Node {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Node::Chord(chord) if f.alternate() => write!(f, "{:#}", chord),
             Node::Chord(chord) => write!(f, "{}", chord),
+            Node::Tuplet(nodes) => {
+                let nodes = nodes.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+                write!(f, "({}){}", nodes.join(" "), nodes.len())
+            }
             Node::Rest => write!(f, "N.C."),
             Node::Sustain => write!(f, "="),
             Node::Repeat => write!(f, "%"),
+            Node::Crescendo => write!(f, "<"),
+            Node::Decrescendo => write!(f, ">"),
+            Node::Subdivision(n) => write!(f, "{{{}}}", n),
+            Node::Voice => write!(f, "&"),
         }
     }
 }
 
+/// writes the modifier with its seventh left to a figure
+fn without_seventh(modifier: &Modifier) -> String {
+    match modifier {
+        Modifier::Major(7) => "M".to_string(),
+        Modifier::Minor(7) => "m".to_string(),
+        Modifier::Dominant(7) => "".to_string(),
+        Modifier::MinorMajaor7 => "mM".to_string(),
+        Modifier::Aug7 => "aug".to_string(),
+        Modifier::Dim7 => "dim".to_string(),
+        m => m.to_string(),
+    }
+}
+
+fn is_half_diminished(chord: &ChordNode) -> bool {
+    chord
+        .semitones()
+        .is_ok_and(|semitones| semitones.into_iter().eq([0, 3, 6, 10]))
+}
+
 impl Display for ChordNode {
+    /// `{:#}` writes the on-chord as figured bass when possible
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mods = self
+        let mut mods = self
             .modifiers
             .iter()
             .map(|m| format!("{}", m))
             .collect::<Vec<_>>()
             .join("");
-        let on = self
+        let mut on = self
             .on
             .as_ref()
             .map(|p| format!("/{}", p))
             .unwrap_or("".to_string());
-        write!(f, "{}{}{}", self.key, mods, on)
+        if let Some(figure) = figure(self).filter(|_| f.alternate()) {
+            // the figure implies the seventh
+            // and `ø` keeps the flat fifth of `m7b5` from running into it
+            if is_half_diminished(self) {
+                mods = "ø".to_string();
+            } else if is_seventh(self) == Some(true) {
+                mods = self
+                    .modifiers
+                    .iter()
+                    .map(without_seventh)
+                    .collect::<Vec<_>>()
+                    .join("");
+            }
+            on = figure.to_string();
+        }
+        let grace = self
+            .grace
+            .as_ref()
+            .map(|g| format!("<{}>", g))
+            .unwrap_or("".to_string());
+        let push = if self.push { ">" } else { "" };
+        let alternate = self
+            .alternate
+            .as_ref()
+            .map(|a| format!("({})", a))
+            .unwrap_or("".to_string());
+        let staccato = if self.staccato { "." } else { "" };
+        let ring = if self.ring { "*" } else { "" };
+        write!(
+            f,
+            "{}{}{}{}{}{}{}{}",
+            push, grace, self.key, mods, on, alternate, staccato, ring
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        export::{Exporter, RechordExporter},
+        import::{first_chord, Importer, RechordImporter},
+    };
+    use anyhow::Result;
+
+    #[test]
+    fn test_extensions() -> Result<()> {
+        for (code, expected, semitones) in [
+            ("Cmaj9", "CM9", vec![0, 4, 7, 11, 14]),
+            ("Cmaj11", "CM11", vec![0, 4, 7, 11, 14, 17]),
+            ("Cmaj13", "CM13", vec![0, 4, 7, 11, 14, 21]),
+            ("C6", "C6", vec![0, 4, 7, 9]),
+            ("C7(b9)", "C7b9", vec![0, 4, 7, 10, 13]),
+        ] {
+            let chord = first_chord(code)?;
+            assert_eq!(chord.to_string(), expected);
+            assert_eq!(first_chord(expected)?, chord);
+            let chord = chord.to_chord()?;
+            assert_eq!(chord.semitones.into_iter().collect::<Vec<_>>(), semitones);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_align() -> Result<()> {
+        let ast = RechordImporter::default()
+            .import("[A]\nC | G7 | Am | F\nDm7 G | C | Fm6 | E7sus4 |")?;
+        let mut buf = vec![];
+        RechordExporter {
+            measures_per_line: 4,
+            align: true,
+        }
+        .export(&mut buf, ast)?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "[A]\n\
+             C     | G7 | Am  | F      |\n\
+             Dm7 G | C  | Fm6 | E7sus4 |\n"
+        );
+        Ok(())
     }
 }
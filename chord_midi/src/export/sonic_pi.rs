@@ -0,0 +1,66 @@
+use super::{to_note_events, Exporter, MidiExporter, SonicPiExporter};
+use crate::model::ast::Ast;
+use anyhow::Result;
+use midi_file::core::NoteNumber;
+use std::io::Write;
+
+/// at 60 bpm seconds are beats
+const BPM: f32 = 60.0;
+
+impl Exporter for SonicPiExporter {
+    /// only the first voice is written, rests become a plain `sleep`
+    fn export(&self, f: &mut impl Write, ast: Ast) -> Result<()> {
+        let exporter = MidiExporter::default();
+        let mut beat = 0.0;
+        for event in to_note_events(ast, self.key, BPM)? {
+            if event.voice != 0 {
+                continue;
+            }
+            if event.start > beat {
+                writeln!(f, "sleep {}", event.start - beat)?;
+            }
+            let notes = event
+                .notes
+                .into_iter()
+                .map(NoteNumber::new)
+                .collect::<Vec<_>>();
+            let names = exporter
+                .note_names(&notes)?
+                .into_iter()
+                .map(|name| format!(":{}", name.to_lowercase().replace('#', "s")))
+                .collect::<Vec<_>>();
+            writeln!(f, "play_chord [{}]", names.join(", "))?;
+            writeln!(f, "sleep {}", event.duration)?;
+            beat = event.start + event.duration;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        export::{Exporter, SonicPiExporter},
+        import::{Importer, RechordImporter},
+        model::pitch::Pitch,
+    };
+    use anyhow::Result;
+
+    #[test]
+    fn test_sonic_pi() -> Result<()> {
+        let ast = RechordImporter::default().import("C G | _ F#m\n")?;
+        let mut buf = vec![];
+        SonicPiExporter { key: Pitch::C }.export(&mut buf, ast)?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "play_chord [:c4, :e4, :g4]\n\
+             sleep 2\n\
+             play_chord [:b3, :d4, :g4]\n\
+             sleep 2\n\
+             sleep 2\n\
+             play_chord [:cs4, :fs4, :a4]\n\
+             sleep 2\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+use super::{Exporter, TransposeAllExporter};
+use crate::model::ast::Ast;
+use crate::model::pitch::Pitch;
+use std::io::Write;
+
+impl Exporter for TransposeAllExporter {
+    fn export(&self, f: &mut impl Write, ast: Ast) -> anyhow::Result<()> {
+        let ast = ast.into_degree(self.key);
+        for semitones in 0..12 {
+            let key = Pitch::try_from((self.key as u8 + semitones) % 12)?;
+            // a comment keeps the output importable
+            writeln!(f, "# Key: {}", key)?;
+            self.chart.export(f, ast.clone().into_pitch(key))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        export::{Exporter, RechordExporter, TransposeAllExporter},
+        import::{Importer, RechordImporter},
+        model::pitch::Pitch,
+    };
+    use anyhow::Result;
+
+    #[test]
+    fn test_transpose_all() -> Result<()> {
+        let ast = RechordImporter::default().import("G D | Em C\n")?;
+        let mut buf = vec![];
+        TransposeAllExporter {
+            key: Pitch::G,
+            chart: RechordExporter::default(),
+        }
+        .export(&mut buf, ast)?;
+        let text = String::from_utf8(buf)?;
+        let sections = text.split("# Key: ").skip(1).collect::<Vec<_>>();
+        assert_eq!(sections.len(), 12);
+        assert!(sections[0].starts_with("G\nG D | Em C"));
+        assert!(sections[1].starts_with("G#\nG# D# | Fm C#"));
+        assert!(sections[5].starts_with("C\nC G | Am F"));
+        assert!(RechordImporter::default().import(&text).is_ok());
+        Ok(())
+    }
+}
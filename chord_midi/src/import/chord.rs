@@ -7,11 +7,13 @@ use crate::model::scale::{Degree, Scale};
 use anyhow::Result;
 use nom::branch::alt;
 use nom::bytes::complete::tag;
-use nom::combinator::{map, opt};
-use nom::multi::{many0, separated_list1};
-use nom::sequence::{delimited, preceded, tuple};
+use nom::character::complete::{digit1, satisfy, space0};
+use nom::combinator::{map, map_opt, map_res, not, opt};
+use nom::multi::{many0, many1, separated_list0};
+use nom::sequence::{delimited, preceded, terminated, tuple};
 use nom::IResult;
-use nom_tracable::tracable_parser;
+use nom_locate::LocatedSpan;
+use nom_tracable::{tracable_parser, TracableInfo};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use std::collections::BTreeSet;
@@ -19,10 +21,12 @@ use std::str::FromStr;
 
 pub static PITCH_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^([CDEFGAB][#b]?)").unwrap());
 
-static DEGREE_NUMBER_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(3|5|6|7|9|11|13)").unwrap());
+/// `1` comes after `11`/`13` so those aren't cut short, it is only meaningful in `omit1`
+static DEGREE_NUMBER_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(11|13|1|3|5|6|7|9)").unwrap());
 
-pub static DEGREE_NAME_REGEX: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"^(IV|VII|VI|V|III|II|I)").unwrap());
+/// whole numerals are taken so `VIII` is rejected instead of read as `VII` and a stray `I`
+pub static DEGREE_NAME_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^([IVX]+)").unwrap());
 
 pub static DEGREE_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^(IV|VII|VI|V|III|II|I)[#b]?").unwrap());
@@ -36,26 +40,96 @@ pub fn parser_roman_num(s: &str) -> Result<u8> {
         "V" => Ok(5),
         "VI" => Ok(6),
         "VII" => Ok(7),
+        _ if !s.is_empty() && s.chars().all(|c| "IVX".contains(c)) => {
+            Err(anyhow::anyhow!("degrees only go up to VII: {}", s))
+        }
         _ => Err(anyhow::anyhow!("invalid degree: {}", s)),
     }
 }
 
+/// returns why a token starting with a roman numeral is not a degree, as for `VIII`
+pub(crate) fn degree_error(token: &str) -> Option<anyhow::Error> {
+    let numeral = token.trim_start_matches(['b', '#']);
+    let cap = DEGREE_NAME_REGEX.captures(numeral)?;
+    parser_roman_num(&cap[1]).err()
+}
+
 #[tracable_parser]
 pub fn node_parser(s: Span) -> IResult<Span, Node> {
     alt((
         map(tag("="), |_| Node::Sustain),
         map(tag("_"), |_| Node::Rest),
         map(tag("%"), |_| Node::Repeat),
-        map(tag("N.C."), |_| Node::Rest),
-        map(chord_node_parser, Node::Chord),
+        map(no_chord_parser, |_| Node::Rest),
+        map(tag("&"), |_| Node::Voice),
+        tuplet_parser,
+        map(preceded(tag(">"), chord_parser), |chord| {
+            Node::Chord(ChordNode {
+                push: true,
+                ..chord
+            })
+        }),
+        map(chord_parser, Node::Chord),
+        map(tag("<"), |_| Node::Crescendo),
+        map(tag(">"), |_| Node::Decrescendo),
+    ))(s)
+}
+
+/// `N.C.`, `NC`, `n.c.` or `tacet`, none of them starts like a pitch or a degree
+#[tracable_parser]
+fn no_chord_parser(s: Span) -> IResult<Span, Span> {
+    terminated(
+        alt((tag("N.C."), tag("NC"), tag("n.c."), tag("tacet"))),
+        not(satisfy(char::is_alphanumeric)),
+    )(s)
+}
+
+/// `(C G Am)3`, the count must match the nodes, which all take a slot
+#[tracable_parser]
+fn tuplet_parser(s: Span) -> IResult<Span, Node> {
+    map_res(
+        tuple((
+            tag("("),
+            many1(delimited(space0, node_parser, space0)),
+            tag(")"),
+            digit1,
+        )),
+        |(_, nodes, _, n): (Span, Vec<Node>, Span, Span)| match n.fragment().parse::<usize>() {
+            Ok(n) if n == nodes.len() && n > 1 && nodes.iter().all(Node::is_timed) => {
+                Ok(Node::Tuplet(nodes))
+            }
+            _ => Err(anyhow::anyhow!(
+                "{} nodes in a tuplet of {}",
+                nodes.len(),
+                n
+            )),
+        },
+    )(s)
+}
+
+/// a chord with an optional grace chord: `<C>G`
+#[tracable_parser]
+fn chord_parser(s: Span) -> IResult<Span, ChordNode> {
+    alt((
+        map(
+            tuple((
+                delimited(tag("<"), chord_node_parser, tag(">")),
+                chord_node_parser,
+            )),
+            |(grace, chord)| ChordNode {
+                grace: Some(Box::new(grace)),
+                ..chord
+            },
+        ),
+        chord_node_parser,
     ))(s)
 }
 
 #[tracable_parser]
 fn key_parser(s: Span) -> IResult<Span, Key> {
     alt((
-        map(pitch_parser, |p| Key::Absolute(p)),
-        map(degree_parser, |d| Key::Relative(d)),
+        map(pitch_parser, Key::Absolute),
+        map(degree_parser, Key::Relative),
     ))(s)
 }
 
@@ -67,8 +141,11 @@ fn chord_node_parser(s: Span) -> IResult<Span, ChordNode> {
             many0(modifier_parser),
             opt(tensions_parser),
             opt(preceded(tag("/"), key_parser)),
+            opt(alternate_parser),
+            opt(tag(".")),
+            opt(tag("*")),
         )),
-        |(key, modifiers, tensions, on)| ChordNode {
+        |(key, modifiers, tensions, on, alternate, staccato, ring)| ChordNode {
             key,
             modifiers: BTreeSet::from_iter(
                 vec![Modifier::Major(5)]
@@ -77,10 +154,48 @@ fn chord_node_parser(s: Span) -> IResult<Span, ChordNode> {
                     .chain(tensions.into_iter().flatten()),
             ),
             on,
+            grace: None,
+            push: false,
+            ring: ring.is_some(),
+            staccato: staccato.is_some(),
+            alternate: alternate.map(Box::new),
         },
     )(s)
 }
 
+/// `(Am)` of `C(Am)`, told from tensions by starting with a key rather than a degree number
+#[tracable_parser]
+fn alternate_parser(s: Span) -> IResult<Span, ChordNode> {
+    delimited(tag("("), chord_node_parser, tag(")"))(s)
+}
+
+/// parses a single chord symbol such as `Cm7` or `G/B`
+impl TryFrom<&str> for ChordNode {
+    type Error = anyhow::Error;
+
+    fn try_from(symbol: &str) -> Result<Self> {
+        let span = LocatedSpan::new_extra(symbol.trim(), TracableInfo::new());
+        match chord_node_parser(span) {
+            Ok((rest, chord)) if rest.is_empty() => Ok(chord),
+            Ok((rest, _)) => Err(anyhow::anyhow!("unknown chord modifier '{}'", rest)),
+            Err(e) => Err(anyhow::anyhow!("invalid chord {}: {:?}", symbol, e)),
+        }
+    }
+}
+
+/// parses a single modifier token such as `m7`, `sus4`, `add9` or `#11`
+impl FromStr for Modifier {
+    type Err = anyhow::Error;
+
+    fn from_str(token: &str) -> Result<Self> {
+        let span = LocatedSpan::new_extra(token, TracableInfo::new());
+        match modifier_parser(span) {
+            Ok((rest, modifier)) if rest.is_empty() => Ok(modifier),
+            _ => Err(anyhow::anyhow!("invalid modifier: {}", token)),
+        }
+    }
+}
+
 #[tracable_parser]
 fn degree_number_parser(s: Span) -> IResult<Span, u8> {
     map(capture(DEGREE_NUMBER_REGEX.to_owned()), |cap| {
@@ -90,8 +205,8 @@ fn degree_number_parser(s: Span) -> IResult<Span, u8> {
 
 #[tracable_parser]
 fn degree_name_parser(s: Span) -> IResult<Span, u8> {
-    map(capture(DEGREE_NAME_REGEX.to_owned()), |cap| {
-        parser_roman_num(&cap[1]).unwrap()
+    map_res(capture(DEGREE_NAME_REGEX.to_owned()), |cap| {
+        parser_roman_num(&cap[1])
     })(s)
 }
 
@@ -104,13 +219,17 @@ fn accidental_parser(s: Span) -> IResult<Span, Accidental> {
 
 #[tracable_parser]
 fn degree_parser(s: Span) -> IResult<Span, u8> {
-    map(tuple((accidental_parser, degree_name_parser)), |(a, d)| {
-        (Scale::Major.semitone(d) as i8 + a as i8) as u8
-    })(s)
+    map(
+        tuple((opt(accidental_parser), degree_name_parser)),
+        |(a, d)| {
+            let a = i8::from(a.unwrap_or(Accidental::Natural));
+            ((Scale::Major.semitone(d) as i8 + a + 12) % 12) as u8
+        },
+    )(s)
 }
 
 #[tracable_parser]
-fn pitch_parser(s: Span) -> IResult<Span, Pitch> {
+pub(crate) fn pitch_parser(s: Span) -> IResult<Span, Pitch> {
     map(capture(PITCH_REGEX.to_owned()), |cap| {
         Pitch::from_str(&cap[1]).unwrap()
     })(s)
@@ -119,42 +238,69 @@ fn pitch_parser(s: Span) -> IResult<Span, Pitch> {
 #[tracable_parser]
 fn modifier_parser(s: Span) -> IResult<Span, Modifier> {
     alt((
+        // before `o` (dim)
+        map(
+            tuple((tag("add"), opt(accidental_parser), degree_number_parser)),
+            |(_, a, d)| Modifier::Add(Degree(d, a.unwrap_or(Accidental::Natural))),
+        ),
+        map(
+            tuple((
+                alt((tag("omit"), tag("no"))),
+                opt(accidental_parser),
+                degree_number_parser,
+            )),
+            |(_, a, d)| Modifier::Omit(Degree(d, a.unwrap_or(Accidental::Natural))),
+        ),
         map(alt((tag("-5"), tag("b5"))), |_| Modifier::Flat5th),
         map(tag("sus2"), |_| Modifier::Sus2),
         map(tag("sus4"), |_| Modifier::Sus4),
-        map(tag("dim7"), |_| Modifier::Dim7),
-        map(alt((tag("dim"), tag("o"))), |_| Modifier::Dim),
+        map(alt((tag("dim7"), tag("o7"))), |_| Modifier::Dim7),
+        // `o` or `+` followed by a letter belongs to a keyword, not the quality
+        map(
+            alt((
+                tag("dim"),
+                terminated(tag("o"), not(satisfy(char::is_alphabetic))),
+            )),
+            |_| Modifier::Dim,
+        ),
         map(tag("aug7"), |_| Modifier::Aug7),
-        map(alt((tag("aug"), tag("+"))), |_| Modifier::Aug),
-        map(tuple((tag("add"), degree_number_parser)), |(_, d)| {
-            Modifier::Add(d)
-        }),
         map(
-            tuple((alt((tag("omit"), tag("no"))), degree_number_parser)),
-            |(_, d)| Modifier::Omit(d),
+            alt((
+                tag("aug"),
+                terminated(tag("+"), not(satisfy(char::is_alphabetic))),
+            )),
+            |_| Modifier::Aug,
         ),
         map(tag("mM7"), |_| Modifier::MinorMajaor7),
         map(
             tuple((alt((tag("maj"), tag("M"))), opt(degree_number_parser))),
             |(_, d)| Modifier::Major(d.unwrap_or(5)),
         ),
-        map(tuple((tag("m"), opt(degree_number_parser))), |(_, d)| {
-            Modifier::Minor(d.unwrap_or(5))
-        }),
+        map(
+            tuple((alt((tag("min"), tag("m"))), opt(degree_number_parser))),
+            |(_, d)| Modifier::Minor(d.unwrap_or(5)),
+        ),
         map(
             tuple((accidental_parser, degree_number_parser)),
             |(a, d)| Modifier::Tension(Degree(d, a)),
         ),
-        map(degree_number_parser, Modifier::Major),
+        // a bare number is dominant except the power chord and the added sixth, the root and third are no chords
+        map_opt(degree_number_parser, |d| match d {
+            5 => Some(Modifier::Power),
+            6 => Some(Modifier::Major(6)),
+            7 | 9 | 11 | 13 => Some(Modifier::Dominant(d)),
+            _ => None,
+        }),
     ))(s)
 }
 
+/// `(b9,#11)`, `()` is left by copy-pasting and has no tensions
 #[tracable_parser]
 fn tensions_parser(s: Span) -> IResult<Span, Vec<Modifier>> {
     map(
         delimited(
             tag("("),
-            separated_list1(
+            separated_list0(
                 tag(","),
                 tuple((opt(accidental_parser), degree_number_parser)),
             ),
@@ -172,11 +318,19 @@ fn tensions_parser(s: Span) -> IResult<Span, Vec<Modifier>> {
 #[cfg(test)]
 mod tests {
     use super::chord_node_parser;
+    use crate::model::{
+        ast::{ChordNode, Node},
+        key::Key,
+        modifier::Modifier,
+        pitch::{Accidental, Pitch},
+        scale::Degree,
+    };
     use anyhow::Result;
     use nom_locate::LocatedSpan;
     use nom_tracable::TracableInfo;
+    use std::collections::BTreeSet;
 
-    fn span<'a>(s: &'a str) -> LocatedSpan<&'a str, TracableInfo> {
+    fn span(s: &str) -> LocatedSpan<&str, TracableInfo> {
         LocatedSpan::new_extra(s, TracableInfo::new())
     }
 
@@ -190,6 +344,155 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_empty_tensions() -> Result<()> {
+        let (res, node) = chord_node_parser(span("C()"))?;
+        assert_eq!(res.into_fragment(), "");
+        assert_eq!(node, chord_node_parser(span("C"))?.1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_from() -> Result<()> {
+        let chord = ChordNode::try_from("Cm7")?;
+        assert_eq!(chord, chord_node_parser(span("Cm7"))?.1);
+        assert!(ChordNode::try_from("Cm7 G").is_err());
+        let ring = ChordNode::try_from("Am7*")?;
+        assert!(ring.ring);
+        assert_eq!(ring.to_string(), "Am7*");
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_chord() -> Result<()> {
+        for nc in ["N.C.", "NC", "n.c.", "tacet"] {
+            let (res, node) = super::node_parser(span(nc))?;
+            assert_eq!(res.into_fragment(), "", "{}", nc);
+            assert_eq!(node, Node::Rest, "{}", nc);
+        }
+        assert!(super::node_parser(span("NCm")).is_err());
+        assert!(super::node_parser(span("tacets")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_modifier_from_str() -> Result<()> {
+        for (token, expected) in [
+            ("m7", Modifier::Minor(7)),
+            ("sus4", Modifier::Sus4),
+            ("add9", Modifier::Add(Degree(9, Accidental::Natural))),
+            ("#11", Modifier::Tension(Degree(11, Accidental::Sharp))),
+            ("7", Modifier::Dominant(7)),
+            ("o", Modifier::Dim),
+        ] {
+            assert_eq!(token.parse::<Modifier>()?, expected, "{}", token);
+        }
+        for token in ["", "x", "m7b5"] {
+            assert!(token.parse::<Modifier>().is_err(), "{}", token);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_staccato() -> Result<()> {
+        let (res, node) = super::node_parser(span("G7. C"))?;
+        assert_eq!(res.into_fragment(), " C");
+        let Node::Chord(chord) = &node else {
+            panic!("not a chord: {:?}", node);
+        };
+        assert!(chord.staccato);
+        assert_eq!(node.to_string(), "G7.");
+        // the dots of `N.C.` still mean no chord
+        assert_eq!(super::node_parser(span("N.C."))?.1, Node::Rest);
+        Ok(())
+    }
+
+    #[test]
+    fn test_alternate() -> Result<()> {
+        let chord = ChordNode::try_from("C(Am)")?;
+        assert_eq!(chord.key, Key::Absolute(Pitch::C));
+        let alternate = chord.alternate.as_deref().unwrap();
+        assert_eq!(alternate, &ChordNode::try_from("Am")?);
+        assert_eq!(chord.to_string(), "C(Am)");
+        // tensions still come first
+        let chord = ChordNode::try_from("C7(b9)(bVII)")?;
+        assert!(chord
+            .modifiers
+            .contains(&Modifier::Tension(Degree(9, Accidental::Flat))));
+        assert_eq!(chord.alternate.unwrap().key, Key::Relative(10));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tuplet() -> Result<()> {
+        let (res, node) = super::node_parser(span("(C G Am)3"))?;
+        assert_eq!(res.into_fragment(), "");
+        let Node::Tuplet(nodes) = &node else {
+            panic!("not a tuplet: {:?}", node);
+        };
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(node.to_string(), "(C G Am)3");
+        assert!(super::node_parser(span("(C G)3")).is_err());
+        // `<` and `{3}` take no slot, so they cannot be counted
+        assert!(super::node_parser(span("(C < G)3")).is_err());
+        assert!(super::node_parser(span("(C {3} G)3")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_grace() -> Result<()> {
+        let (_, node) = super::node_parser(span(">G"))?;
+        assert!(matches!(node, Node::Chord(ChordNode { push: true, .. })));
+        let (_, node) = super::node_parser(span(">"))?;
+        assert_eq!(node, Node::Decrescendo);
+        let (res, node) = super::node_parser(span("<C>G"))?;
+        assert_eq!(res.into_fragment(), "");
+        let Node::Chord(chord) = node else {
+            panic!("not a chord: {:?}", node);
+        };
+        assert_eq!(chord.key, Key::Absolute(Pitch::G));
+        assert_eq!(chord.grace.unwrap().key, Key::Absolute(Pitch::C));
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_accidental() -> Result<()> {
+        for (chord, expected) in [
+            ("Caddb9", vec![0, 4, 7, 13]),
+            ("Cadd#11", vec![0, 4, 7, 18]),
+        ] {
+            let (_, node) = chord_node_parser(span(chord))?;
+            assert_eq!(node.semitones()?, BTreeSet::from_iter(expected));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_dim_aug_symbols() -> Result<()> {
+        let parse = |s| -> Result<(String, ChordNode)> {
+            let (res, node) = chord_node_parser(span(s))?;
+            Ok((res.into_fragment().to_string(), node))
+        };
+        for (symbol, name) in [("Co", "Cdim"), ("C+", "Caug"), ("Co7", "Cdim7")] {
+            let (res, node) = parse(symbol)?;
+            assert_eq!(res, "");
+            assert_eq!(node.semitones()?, parse(name)?.1.semitones()?);
+        }
+        assert_eq!(parse("Cdim")?.0, "");
+        assert_eq!(parse("Caug")?.0, "");
+        assert_eq!(
+            parse("Cdim7")?.1.semitones()?,
+            BTreeSet::from_iter(vec![0, 3, 6, 9])
+        );
+        // the following text is left to the caller
+        assert_eq!(parse("C G")?.0, " G");
+        assert_eq!(parse("Cdim G")?.0, " G");
+        assert_eq!(parse("Coops")?.0, "oops");
+        assert_eq!(parse("C+oops")?.0, "+oops");
+        assert_eq!(parse("Comit5")?.0, "");
+        Ok(())
+    }
+
     #[test]
     fn test_chord_node_parser() -> Result<()> {
         for chord in [
@@ -207,6 +510,9 @@ mod tests {
             "C7sus4(b9)",
             "C7(13)",
             "AbmM7/Eb",
+            "Caddb9",
+            "Cadd#11",
+            "C7omitb5",
         ] {
             let span = span(chord);
             let (res, _ast) = chord_node_parser(span)?;
@@ -214,4 +520,22 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_major_minor() -> Result<()> {
+        for (chord, expected) in [
+            ("Cmaj7", vec![Modifier::Major(5), Modifier::Major(7)]),
+            ("CM7", vec![Modifier::Major(5), Modifier::Major(7)]),
+            ("Cmaj", vec![Modifier::Major(5)]),
+            ("Cm", vec![Modifier::Major(5), Modifier::Minor(5)]),
+            ("Cm7", vec![Modifier::Major(5), Modifier::Minor(7)]),
+            ("Cmin7", vec![Modifier::Major(5), Modifier::Minor(7)]),
+            ("C5", vec![Modifier::Major(5), Modifier::Power]),
+        ] {
+            let (res, node) = chord_node_parser(span(chord))?;
+            assert_eq!(res.into_fragment(), "", "{}", chord);
+            assert_eq!(node.modifiers, BTreeSet::from_iter(expected), "{}", chord);
+        }
+        Ok(())
+    }
 }
@@ -1,4 +1,9 @@
-use crate::model::ast::Ast;
+#[cfg(test)]
+use crate::model::ast::ChordNode;
+use crate::model::{
+    ast::{measure_slots, voices, Ast, Node},
+    key::Key,
+};
 
 mod chord;
 mod parser_util;
@@ -9,8 +14,135 @@ pub trait Importer {
     fn import(&self, code: &str) -> anyhow::Result<Ast>;
 }
 
-#[derive(Debug)]
-pub struct RechordImporter;
+#[derive(Debug, Default)]
+pub struct RechordImporter {
+    /// accept lower-case pitches and case-insensitive quality keywords (`cmaj7`, `CMAJ7`)
+    pub lenient: bool,
+    /// accept `,` between nodes (`C, G, Am, F`); commas inside tension parens are kept
+    pub commas: bool,
+    /// require one node per beat of 4/4 (`=` included) instead of spreading nodes over the bar
+    pub chord_per_beat: bool,
+}
+
+/// imports the chart and returns its first chord, for tests that check a single symbol
+#[cfg(test)]
+pub(crate) fn first_chord(code: &str) -> anyhow::Result<ChordNode> {
+    match RechordImporter::default().import(code)?.into_iter().next() {
+        Some(Node::Chord(chord)) => Ok(chord),
+        _ => Err(anyhow::anyhow!("not a chord: {}", code)),
+    }
+}
+
+/// an issue found by `RechordImporter::validate`, `line` is 1-origin and 0 for the whole chart
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub message: String,
+}
+
+impl RechordImporter {
+    /// returns every parse and semantic issue of the chart instead of stopping at the first.
+    /// lines are parsed on their own to locate issues, but parse errors are only reported when
+    /// the whole chart fails, as `%%` and `\\` need the lines around them
+    pub fn validate(&self, code: &str) -> Vec<Diagnostic> {
+        let code = code.replace("\r\n", "\n").replace('\r', "\n");
+        let whole = self.parse(&code).ok();
+        let mut diagnostics = vec![];
+        let mut score = vec![];
+        for (i, line) in code.split('\n').enumerate() {
+            let mut report = |message: String| {
+                diagnostics.push(Diagnostic {
+                    line: i + 1,
+                    message,
+                })
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let items = match self.parse(&format!("{}\n", line)) {
+                Ok(Ast::Score(items)) => items,
+                Ok(ast) => vec![Box::new(ast)],
+                Err(e) => {
+                    if whole.is_none() {
+                        report(e.to_string());
+                    }
+                    continue;
+                }
+            };
+            for ast in &items {
+                let Ast::Measure(nodes, _) = &**ast else {
+                    continue;
+                };
+                for voice in voices(nodes) {
+                    if let Err(e) = measure_slots(voice) {
+                        report(e.to_string());
+                    }
+                }
+                for node in nodes {
+                    let Node::Chord(chord) = node else {
+                        continue;
+                    };
+                    if let Key::Relative(_) = chord.key {
+                        report(format!("degree chord {} without a key", chord));
+                    } else if let Err(e) = chord.to_chord() {
+                        report(format!("{}: {}", chord, e));
+                    }
+                }
+            }
+            score.extend(items);
+        }
+        if let Err(e) = rechord::check_bars(&whole.unwrap_or(Ast::Score(score))) {
+            diagnostics.push(Diagnostic {
+                line: 0,
+                message: e.to_string(),
+            });
+        }
+        diagnostics
+    }
+}
 
 #[derive(Debug)]
 pub struct SexpImporter;
+
+#[cfg(test)]
+mod tests {
+    use super::{Diagnostic, Importer, RechordImporter};
+
+    #[test]
+    fn test_validate() {
+        let importer = RechordImporter::default();
+        assert_eq!(importer.validate("C G | Am F\n"), vec![]);
+        // lines that only parse along with their neighbours are fine
+        let code = "@bars 6\n[C] I IV | G \\\nC |\nC G | Am F\n%%\n";
+        assert!(importer.import(code).is_ok());
+        assert_eq!(importer.validate(code), vec![]);
+        // a parse error doesn't hide the next line
+        let diagnostics = importer.validate("C ?\nI\n");
+        assert_eq!(
+            diagnostics.iter().map(|d| d.line).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        let diagnostics = importer.validate("C G | {2} Am F C\nF\nI IV\n@bars 3\n");
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic {
+                    line: 1,
+                    message: "3 nodes in a measure of 2 subdivisions".to_string(),
+                },
+                Diagnostic {
+                    line: 3,
+                    message: "degree chord I without a key".to_string(),
+                },
+                Diagnostic {
+                    line: 3,
+                    message: "degree chord IV without a key".to_string(),
+                },
+                Diagnostic {
+                    line: 0,
+                    message: "expected 3 bars, found 4".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -1,70 +1,564 @@
 use super::parser_util::Span;
-use crate::import::chord::node_parser;
+use crate::import::chord::{degree_error, node_parser, pitch_parser};
 use crate::import::RechordImporter;
-use crate::model::ast::Ast;
+use crate::model::ast::{voices, Ast, ChordNode, Directive, Node};
+use crate::model::key::Key;
+use crate::model::modifier::Modifier;
+use crate::model::pitch::Pitch;
+use crate::model::scale::Scale;
 use anyhow::Result;
 use nom::branch::alt;
-use nom::bytes::complete::tag;
-use nom::character::complete::{line_ending, not_line_ending, space0};
-use nom::combinator::{eof, map, value};
+use nom::bytes::complete::{is_not, tag};
+use nom::character::complete::{digit1, line_ending, not_line_ending, space0, space1};
+use nom::combinator::{eof, map, map_res, opt, recognize, value};
 use nom::multi::{many0, many1};
-use nom::sequence::{delimited, tuple};
+use nom::sequence::{delimited, preceded, tuple};
 use nom::IResult;
 use nom_locate::LocatedSpan;
 use nom_tracable::tracable_parser;
 use nom_tracable::TracableInfo;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+
+static LENIENT_PITCH_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(^|[\s|/])([a-g])([IVX]?)").unwrap());
+
+/// a root followed by the rest of its chord, up to the next space, barline or on-chord
+static LENIENT_CHORD_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(^|[\s|/(])([A-G][#b]?|[b#]?[IV]+)([^\s|/()]*)").unwrap());
+
+static LENIENT_QUALITY_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)maj|min|sus|dim|aug|add|omit").unwrap());
+
+/// `#` or `//` comment lines, which the text passes below leave alone
+fn is_comment(line: &str) -> bool {
+    line.trim_start().starts_with('#') || line.trim_start().starts_with("//")
+}
+
+/// upper-cases pitch letters at the start of a chord or on-chord and lower-cases quality keywords
+/// following a root. a `b` following a pitch letter, before a numeral or inside tensions is left
+/// as a flat. comments, markers and directives are left alone.
+fn normalize_lenient(code: &str) -> String {
+    code.split('\n')
+        .map(|line| {
+            let trimmed = line.trim();
+            let marker = trimmed.starts_with('[') && trimmed.ends_with(']');
+            if is_comment(line) || trimmed.starts_with('@') || marker {
+                return line.to_string();
+            }
+            // `b` before a numeral is the flat of a degree, as in `bVII`
+            let line = LENIENT_PITCH_REGEX.replace_all(line, |cap: &Captures| match &cap[3] {
+                "" => format!("{}{}", &cap[1], cap[2].to_uppercase()),
+                _ => cap[0].to_string(),
+            });
+            LENIENT_CHORD_REGEX
+                .replace_all(&line, |cap: &Captures| {
+                    let quality = LENIENT_QUALITY_REGEX.replace_all(&cap[3], |cap: &Captures| {
+                        match cap[0].to_lowercase().as_str() {
+                            "min" => "m".to_string(),
+                            q => q.to_string(),
+                        }
+                    });
+                    format!("{}{}{}", &cap[1], &cap[2], quality)
+                })
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// joins a line ending with `\\` to the next one, so a measure can wrap without a barline
+fn join_continued_lines(code: &str) -> String {
+    let mut joined = String::new();
+    for line in code.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        match trimmed.strip_suffix('\\') {
+            Some(head) if !is_comment(trimmed) => {
+                joined.push_str(head);
+                joined.push(' ');
+            }
+            _ => joined.push_str(line),
+        }
+    }
+    joined
+}
+
+/// replaces commas outside `(...)` and `[...]` with spaces, leaving comment lines alone
+fn normalize_commas(code: &str) -> String {
+    code.split('\n')
+        .map(|line| {
+            if is_comment(line) {
+                return line.to_string();
+            }
+            let mut depth = 0;
+            line.chars()
+                .map(|c| {
+                    match c {
+                        '(' | '[' => depth += 1,
+                        ')' | ']' => depth -= 1,
+                        ',' if depth == 0 => return ' ',
+                        _ => {}
+                    }
+                    c
+                })
+                .collect()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
 
 impl super::Importer for RechordImporter {
     fn import(&self, code: &str) -> Result<Ast> {
-        let code = code.replace("♭", "b");
+        let ast = self.parse(code)?;
+        check_bars(&ast)?;
+        if self.chord_per_beat {
+            check_beats(&ast)?;
+        }
+        Ok(ast)
+    }
+}
+
+impl RechordImporter {
+    /// normalizes and parses the chart without checking it against `@bars`
+    pub(super) fn parse(&self, code: &str) -> Result<Ast> {
+        // CR+LF and CR to LF
+        let mut code = code.replace("\r\n", "\n").replace('\r', "\n");
+        code = code.replace("♭", "b");
+        code = join_continued_lines(&code);
+        // superscript and subscript digits copied from PDFs
+        code = code
+            .chars()
+            .map(|c| match c {
+                '⁰' | '₀' => '0',
+                '¹' | '₁' => '1',
+                '²' | '₂' => '2',
+                '³' | '₃' => '3',
+                '⁴' | '₄' => '4',
+                '⁵' | '₅' => '5',
+                '⁶' | '₆' => '6',
+                '⁷' | '₇' => '7',
+                '⁸' | '₈' => '8',
+                '⁹' | '₉' => '9',
+                c => c,
+            })
+            .collect();
+        if self.lenient {
+            code = normalize_lenient(&code);
+        }
+        if self.commas {
+            code = normalize_commas(&code);
+        }
         let span = LocatedSpan::new_extra(code.as_str(), TracableInfo::new());
-        let (rest, ast) = ast_parser(span).map_err(|e| anyhow::anyhow!("parse error: {:?}", e))?;
+        let (rest, items) = ast_parser(span).map_err(|e| {
+            diagnose(&code).unwrap_or_else(|| anyhow::anyhow!("parse error: {:?}", e))
+        })?;
         if !rest.is_empty() {
-            return Err(anyhow::anyhow!("parse error: {:?}", rest));
+            return Err(
+                diagnose(&code).unwrap_or_else(|| anyhow::anyhow!("parse error: {:?}", rest))
+            );
         }
-        Ok(ast)
+        expand_repeats(items)
     }
 }
 
+/// finds the first token that fails for a known reason: a chord followed by text
+/// no modifier matches, as in `Czyx`, or a numeral past `VII`
+fn diagnose(code: &str) -> Option<anyhow::Error> {
+    for (i, line) in code.split('\n').enumerate() {
+        // comments, markers and directives parse on their own line
+        let item = format!("{}\n", line);
+        let item = LocatedSpan::new_extra(item.as_str(), TracableInfo::new());
+        if alt((comment_parser, marker_parser, directive_parser))(item).is_ok() {
+            continue;
+        }
+        let mut rest = LocatedSpan::new_extra(line, TracableInfo::new());
+        let mut chord = None;
+        loop {
+            let trimmed = rest.trim_start_matches([' ', '\t', '|']);
+            let spaced = trimmed.len() != rest.len();
+            if trimmed.is_empty() {
+                break;
+            }
+            rest = nom::Slice::slice(&rest, rest.len() - trimmed.len()..);
+            if let Ok((next, _)) = alt((recognize(key_parser), recognize(subdivision_parser)))(rest)
+            {
+                chord = None;
+                rest = next;
+                continue;
+            }
+            match node_parser(rest) {
+                Ok((next, node)) => {
+                    let len = rest.len() - next.len();
+                    chord = match node {
+                        Node::Chord(_) => Some(&rest.fragment()[..len]),
+                        _ => None,
+                    };
+                    rest = next;
+                }
+                Err(_) => {
+                    let word = trimmed.split([' ', '\t', '|']).next().unwrap_or_default();
+                    if let Some(chord) = chord.filter(|_| !spaced) {
+                        return Some(anyhow::anyhow!(
+                            "line {}: unknown chord modifier '{}' after {}",
+                            i + 1,
+                            word,
+                            chord
+                        ));
+                    }
+                    return degree_error(word).map(|e| anyhow::anyhow!("line {}: {}", i + 1, e));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// checks the measure count against `@bars`
+pub(super) fn check_bars(ast: &Ast) -> Result<()> {
+    let Ast::Score(score) = ast else {
+        return Ok(());
+    };
+    let measures = score
+        .iter()
+        .filter(|ast| matches!(***ast, Ast::Measure(_, _)))
+        .count();
+    for ast in score {
+        if let Ast::Directive(Directive::Bars(bars)) = **ast {
+            if bars != measures {
+                return Err(anyhow::anyhow!(
+                    "expected {} bars, found {}",
+                    bars,
+                    measures
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// beats in a measure of 4/4
+const BEATS: usize = 4;
+
+/// checks each voice of each measure fills `BEATS`, or `n` after a leading `{n}`
+fn check_beats(ast: &Ast) -> Result<()> {
+    let Ast::Score(score) = ast else {
+        return Ok(());
+    };
+    let measures = score.iter().filter_map(|ast| match &**ast {
+        Ast::Measure(nodes, _) => Some(nodes),
+        _ => None,
+    });
+    for (i, nodes) in measures.enumerate() {
+        for voice in voices(nodes) {
+            let expected = match voice.first() {
+                Some(Node::Subdivision(n)) => *n as usize,
+                _ => BEATS,
+            };
+            let beats = voice.iter().map(|n| n.width()).sum::<usize>();
+            if beats != expected {
+                return Err(anyhow::anyhow!(
+                    "measure {}: {} beats, expected {}",
+                    i + 1,
+                    beats,
+                    expected
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// a line of the chart, `%%` is expanded once the measures before it are known
+#[derive(Debug)]
+enum Item {
+    Ast(Ast),
+    // br?
+    TwoBarRepeat(bool),
+}
+
 #[tracable_parser]
-fn ast_parser(s: Span) -> IResult<Span, Ast> {
+fn ast_parser(s: Span) -> IResult<Span, Vec<Item>> {
     map(
-        tuple((many1(alt((comment_parser, measure_parser))), eof)),
-        |(score, _)| Ast::Score(score.into_iter().map(Box::new).collect()),
+        tuple((
+            many1(alt((
+                map(comment_parser, Item::Ast),
+                map(marker_parser, Item::Ast),
+                map(directive_parser, Item::Ast),
+                two_bar_repeat_parser,
+                map(measure_parser, Item::Ast),
+            ))),
+            eof,
+        )),
+        |(items, _)| items,
     )(s)
 }
 
+/// builds the score, replacing each `%%` with copies of the two measures before it
+fn expand_repeats(items: Vec<Item>) -> Result<Ast> {
+    let mut score: Vec<Box<Ast>> = vec![];
+    for item in items {
+        let ast = match item {
+            Item::Ast(ast) => ast,
+            Item::TwoBarRepeat(br) => {
+                let mut measures = score
+                    .iter()
+                    .rev()
+                    .filter_map(|ast| match &**ast {
+                        Ast::Measure(nodes, _) => Some(nodes.clone()),
+                        _ => None,
+                    })
+                    .take(2)
+                    .collect::<Vec<_>>();
+                if measures.len() < 2 {
+                    return Err(anyhow::anyhow!("%% needs two bars before it"));
+                }
+                let (last, first) = (measures.remove(0), measures.remove(0));
+                score.push(Box::new(Ast::Measure(first, false)));
+                Ast::Measure(last, br)
+            }
+        };
+        score.push(Box::new(ast));
+    }
+    Ok(Ast::Score(score))
+}
+
+/// `%%`: repeats the two measures before it
+#[tracable_parser]
+fn two_bar_repeat_parser(s: Span) -> IResult<Span, Item> {
+    map(
+        tuple((
+            opt(tuple((space0, tag("|")))),
+            delimited(space0, tag("%%"), space0),
+            measure_sep,
+            space_or_line_ending_many0,
+        )),
+        |(_, _, br, _)| Item::TwoBarRepeat(br),
+    )(s)
+}
+
+/// `# ...` or `// ...` on its own line
 #[tracable_parser]
 fn comment_parser(s: Span) -> IResult<Span, Ast> {
     map(
-        tuple((tag("#"), not_line_ending, line_ending)),
+        tuple((alt((tag("#"), tag("//"))), not_line_ending, line_ending)),
         |(_, comment, _): (Span, Span, Span)| Ast::Comment(comment.to_string()),
     )(s)
 }
 
+/// `[Verse]` alone on its line. `[A]` followed by chords on the same line is a key prefix instead
+#[tracable_parser]
+fn marker_parser(s: Span) -> IResult<Span, Ast> {
+    map(
+        tuple((
+            tag("["),
+            is_not("]\r\n"),
+            tag("]"),
+            space0,
+            alt((line_ending, eof)),
+        )),
+        |(_, label, _, _, _): (Span, Span, Span, Span, Span)| Ast::Marker(label.to_string()),
+    )(s)
+}
+
+/// a number following spaces
+fn number_parser<T: std::str::FromStr>(s: Span) -> IResult<Span, T> {
+    map_res(preceded(space1, digit1), |d: Span| {
+        d.fragment().parse::<T>()
+    })(s)
+}
+
+/// `@dynamics cresc 60 100 4`
+#[tracable_parser]
+fn dynamics_parser(s: Span) -> IResult<Span, Directive> {
+    map_res(
+        tuple((
+            tag("@dynamics"),
+            preceded(space1, alt((tag("cresc"), tag("dim")))),
+            number_parser::<u8>,
+            number_parser::<u8>,
+            number_parser::<u32>,
+        )),
+        |(_, kind, from, to, measures): (Span, Span, u8, u8, u32)| {
+            if !(1..=127).contains(&from) || !(1..=127).contains(&to) {
+                return Err(anyhow::anyhow!("velocity out of range: {} {}", from, to));
+            }
+            if measures == 0 {
+                return Err(anyhow::anyhow!("empty dynamics span"));
+            }
+            match (*kind.fragment(), from <= to) {
+                ("cresc", true) | ("dim", false) => Ok(Directive::Dynamics { from, to, measures }),
+                _ => Err(anyhow::anyhow!("{} from {} to {}", kind, from, to)),
+            }
+        },
+    )(s)
+}
+
+/// `@octave -1`
+#[tracable_parser]
+fn octave_parser(s: Span) -> IResult<Span, Directive> {
+    map_res(
+        preceded(
+            tuple((tag("@octave"), space1)),
+            recognize(tuple((opt(tag("-")), digit1))),
+        ),
+        |n: Span| n.fragment().parse::<i8>().map(Directive::Octave),
+    )(s)
+}
+
+/// note values of `@tempo` in sixteenths: whole, half, quarter and eighth
+const TEMPO_BEATS: [(&str, u32); 4] = [("w", 16), ("h", 8), ("q", 4), ("e", 2)];
+
+/// `@tempo q=120`, `h=60` or dotted `q.=80`
+#[tracable_parser]
+fn tempo_parser(s: Span) -> IResult<Span, Directive> {
+    map_res(
+        tuple((
+            tag("@tempo"),
+            space1,
+            is_not(".= \t\r\n"),
+            opt(tag(".")),
+            tag("="),
+            recognize(tuple((digit1, opt(tuple((tag("."), digit1)))))),
+        )),
+        |(_, _, note, dot, _, n): (Span, Span, Span, Option<Span>, Span, Span)| {
+            let Some((_, beat)) = TEMPO_BEATS.iter().find(|(name, _)| name == note.fragment())
+            else {
+                return Err(anyhow::anyhow!("unknown note value: {}", note));
+            };
+            let beat = if dot.is_some() { beat * 3 / 2 } else { *beat };
+            match n.fragment().parse::<f32>()? {
+                per_minute if per_minute > 0.0 => Ok(Directive::Tempo { beat, per_minute }),
+                per_minute => Err(anyhow::anyhow!("invalid tempo: {}", per_minute)),
+            }
+        },
+    )(s)
+}
+
+/// `@vel 90` or `@vel reset`
+#[tracable_parser]
+fn velocity_parser(s: Span) -> IResult<Span, Directive> {
+    preceded(
+        tag("@vel"),
+        alt((
+            map(preceded(space1, tag("reset")), |_| {
+                Directive::Velocity(None)
+            }),
+            map_res(number_parser::<u8>, |v| match v {
+                1..=127 => Ok(Directive::Velocity(Some(v))),
+                v => Err(anyhow::anyhow!("velocity out of range: {}", v)),
+            }),
+        )),
+    )(s)
+}
+
+/// `@bars 32`
+#[tracable_parser]
+fn bars_parser(s: Span) -> IResult<Span, Directive> {
+    map(
+        preceded(tag("@bars"), number_parser::<usize>),
+        Directive::Bars,
+    )(s)
+}
+
+#[tracable_parser]
+fn directive_parser(s: Span) -> IResult<Span, Ast> {
+    map(
+        tuple((
+            alt((
+                dynamics_parser,
+                bars_parser,
+                octave_parser,
+                tempo_parser,
+                velocity_parser,
+            )),
+            space0,
+            alt((line_ending, eof)),
+        )),
+        |(directive, _, _)| Ast::Directive(directive),
+    )(s)
+}
+
 fn measure_sep(s: Span) -> IResult<Span, bool> {
-    alt((value(false, tag("|")), value(true, line_ending)))(s)
+    alt((
+        value(true, tuple((tag("|"), space0, line_ending))),
+        value(false, tag("|")),
+        value(true, line_ending),
+        value(true, eof),
+    ))(s)
 }
 
 fn space_or_line_ending_many0(s: Span) -> IResult<Span, ()> {
     value((), many0(alt((tag(" "), line_ending))))(s)
 }
 
+/// `{3}`
+#[tracable_parser]
+fn subdivision_parser(s: Span) -> IResult<Span, Node> {
+    map_res(delimited(tag("{"), digit1, tag("}")), |n: Span| {
+        n.fragment().parse::<u8>().map(Node::Subdivision)
+    })(s)
+}
+
+/// `[A]` or `[Am]` before a measure: degrees in the measure are resolved in the key, returned with
+/// whether it is minor. only degree chords change, so `[A] C G` is the same as `C G`, while `[A]`
+/// alone on its line is a marker
+#[tracable_parser]
+fn key_parser(s: Span) -> IResult<Span, (Pitch, bool)> {
+    delimited(
+        tag("["),
+        tuple((pitch_parser, map(opt(tag("m")), |m| m.is_some()))),
+        tag("]"),
+    )(s)
+}
+
+/// gives a degree chord without a quality the triad it has in the natural minor scale,
+/// `I IV V` are `Im IVm Vm` in a minor key
+fn into_minor(chord: ChordNode) -> ChordNode {
+    let Key::Relative(semitone) = chord.key else {
+        return chord;
+    };
+    let bare = chord.modifiers.iter().all(|m| *m == Modifier::Major(5));
+    let degree = (1..=7).find(|d| Scale::Minor.semitone(*d) == semitone);
+    match degree.map(|d| Scale::Minor.chord_for_degree(Pitch::C, d)) {
+        Some(Ok(minor)) if bare => ChordNode {
+            modifiers: minor.modifiers,
+            ..chord
+        },
+        _ => chord,
+    }
+}
+
 #[tracable_parser]
 fn measure_parser(s: Span) -> IResult<Span, Ast> {
     map(
         tuple((
+            // leading barline: `| C G | Am F |`
+            opt(tuple((space0, tag("|")))),
+            opt(delimited(space0, key_parser, space0)),
+            opt(delimited(space0, subdivision_parser, space0)),
             many1(delimited(space0, node_parser, space0)),
             measure_sep,
             space_or_line_ending_many0,
         )),
-        |(nodes, br, _)| Ast::Measure(nodes, br),
+        |(_, key, subdivision, nodes, br, _)| {
+            let nodes = subdivision.into_iter().chain(nodes);
+            match key {
+                Some((key, true)) => {
+                    let nodes = nodes.map(|node| node.map_chords(&into_minor));
+                    Ast::Measure(nodes.collect(), br).into_pitch(key)
+                }
+                Some((key, false)) => Ast::Measure(nodes.collect(), br).into_pitch(key),
+                None => Ast::Measure(nodes.collect(), br),
+            }
+        },
     )(s)
 }
 
 #[cfg(test)]
 mod tests {
     use super::{ast_parser, measure_parser};
+    use crate::import::{Importer, RechordImporter};
+    use crate::model::ast::{Ast, Directive};
     use anyhow::Result;
     use nom_locate::LocatedSpan;
     use nom_tracable::TracableInfo;
@@ -75,7 +569,7 @@ mod tests {
 
     #[test]
     fn test_measure_parser() -> Result<()> {
-        for measure in ["C\n"] {
+        for measure in ["C\n", "C G|"] {
             let span = span(measure);
             let (res, _ast) = measure_parser(span)?;
             assert_eq!(res.into_fragment(), "");
@@ -87,9 +581,297 @@ mod tests {
     fn test_ast_parser() -> Result<()> {
         for score in ["# comment\nCCC", "CCC|", "CCC\n"] {
             let span = span(score);
-            let (res, _ast) = ast_parser(span)?;
+            let (res, _items) = ast_parser(span)?;
             assert_eq!(res.into_fragment(), "");
         }
         Ok(())
     }
+
+    #[test]
+    fn test_leading_barline() -> Result<()> {
+        let importer = RechordImporter::default();
+        assert_eq!(
+            importer.import("| C G | Am F |\n| Dm |")?,
+            importer.import("C G | Am F\nDm |")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_marker() -> Result<()> {
+        let ast = RechordImporter::default().import("[Verse]\nC | G\n[Chorus]\nAm\n")?;
+        let Ast::Score(score) = ast else {
+            panic!("not a score");
+        };
+        assert_eq!(*score[0], Ast::Marker("Verse".to_string()));
+        assert_eq!(*score[3], Ast::Marker("Chorus".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_inline_key() -> Result<()> {
+        let ast = RechordImporter::default().import("[C] I IV V | [Am] I IV V |\n[A]\nI\n")?;
+        let expected = RechordImporter::default().import("C F G | Am Dm Em |\n[A]\nI\n")?;
+        assert_eq!(ast, expected);
+        // qualities and degrees off the minor scale are kept
+        let ast = RechordImporter::default().import("[Am] I7 V7 bIII bVI #IV\n")?;
+        let expected = RechordImporter::default().import("A7 E7 C F D#\n")?;
+        assert_eq!(ast, expected);
+        // a key before absolute chords changes nothing
+        let ast = RechordImporter::default().import("[A] C G\n")?;
+        assert_eq!(ast, RechordImporter::default().import("C G\n")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_subdivision() -> Result<()> {
+        let ast = RechordImporter::default().import("| {3} C G Am |\n")?;
+        assert_eq!(ast.to_string(), "{3} C G Am | \n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_commas() -> Result<()> {
+        let importer = RechordImporter {
+            commas: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            importer.import("C, G, Am, F\n")?,
+            RechordImporter::default().import("C G Am F\n")?
+        );
+        assert_eq!(
+            importer.import("C7(9,13), F\n")?,
+            RechordImporter::default().import("C7(9,13) F\n")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_endings() -> Result<()> {
+        let importer = RechordImporter::default();
+        let expected = importer.import("# intro\nC G |\nAm F |\n")?;
+        assert_eq!(importer.import("# intro\rC G |\rAm F |\r")?, expected);
+        assert_eq!(importer.import("# intro\r\nC G |\r\nAm F |\r\n")?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dynamics() -> Result<()> {
+        let importer = RechordImporter::default();
+        let ast = importer.import("@dynamics dim 100 60 4\nC | G\n")?;
+        let Ast::Score(score) = &ast else { panic!() };
+        assert_eq!(
+            *score[0],
+            Ast::Directive(Directive::Dynamics {
+                from: 100,
+                to: 60,
+                measures: 4
+            })
+        );
+        assert!(ast.to_string().starts_with("@dynamics dim 100 60 4\n"));
+        assert!(importer.import("@dynamics cresc 100 60 4\nC\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tempo() -> Result<()> {
+        let importer = RechordImporter::default();
+        for (code, qpm) in [
+            ("q=120", 120.0),
+            ("h=60", 120.0),
+            ("q.=80", 120.0),
+            ("e=92.5", 46.25),
+        ] {
+            let ast = importer.import(&format!("@tempo {}\nC\n", code))?;
+            let Ast::Score(score) = &ast else { panic!() };
+            let Ast::Directive(directive) = &*score[0] else {
+                panic!("not a directive: {:?}", score[0]);
+            };
+            assert_eq!(directive.quarters_per_minute(), Some(qpm), "{}", code);
+            assert!(ast.to_string().starts_with(&format!("@tempo {}\n", code)));
+        }
+        assert!(importer.import("@tempo x=120\nC\n").is_err());
+        assert!(importer.import("@tempo q=0\nC\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_bars() -> Result<()> {
+        let importer = RechordImporter::default();
+        assert!(importer.import("@bars 2\nC | G\n").is_ok());
+        let err = importer.import("@bars 4\nC | G |\nAm\n").unwrap_err();
+        assert_eq!(err.to_string(), "expected 4 bars, found 3");
+        Ok(())
+    }
+
+    #[test]
+    fn test_enharmonic() -> Result<()> {
+        let importer = RechordImporter::default();
+        assert_eq!(
+            importer.import("Cb Fb E# B#\n")?,
+            importer.import("B E F C\n")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_modifier() -> Result<()> {
+        let importer = RechordImporter::default();
+        let e = importer.import("C G | Am F\nC Czyx G\n").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "line 2: unknown chord modifier 'zyx' after C"
+        );
+        let e = importer.import("Cm7zyx\n").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "line 1: unknown chord modifier 'zyx' after Cm7"
+        );
+        // markers and directives before the broken line are skipped
+        let e = importer
+            .import("[Intro]\n@tempo q=90\nC Czyx G\n")
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "line 3: unknown chord modifier 'zyx' after C"
+        );
+        // other errors keep the parser's message
+        let e = importer.import("C ?\n").unwrap_err();
+        assert!(e.to_string().starts_with("parse error"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_degree_past_seven() -> Result<()> {
+        let importer = RechordImporter::default();
+        let e = importer.import("[C] I IV | VIII\n").unwrap_err();
+        assert_eq!(e.to_string(), "line 1: degrees only go up to VII: VIII");
+        let e = importer.import("bVIII7\n").unwrap_err();
+        assert_eq!(e.to_string(), "line 1: degrees only go up to VII: VIII");
+        assert!(importer.import("[C] VII bVII\n").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_slash_comment() -> Result<()> {
+        let importer = RechordImporter {
+            lenient: true,
+            commas: true,
+            ..RechordImporter::default()
+        };
+        let ast = importer.import("// intro, a capo 2\nC# G | Am F\n# verse\n")?;
+        let Ast::Score(score) = &ast else { panic!() };
+        assert_eq!(*score[0], Ast::Comment(" intro, a capo 2".to_string()));
+        assert_eq!(*score[3], Ast::Comment(" verse".to_string()));
+        let expected = importer.import("C# G | Am F\n")?;
+        assert_eq!(
+            ast.into_iter().collect::<Vec<_>>(),
+            expected.into_iter().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_continuation() -> Result<()> {
+        let importer = RechordImporter::default();
+        let ast = importer.import("C G \\\n Am F |\n")?;
+        assert_eq!(ast, importer.import("C G Am F |\n")?);
+        let Ast::Score(score) = ast else {
+            panic!("not a score");
+        };
+        assert_eq!(score.len(), 1);
+        // comments keep their backslash
+        let ast = importer.import("# a \\\nC\n")?;
+        let Ast::Score(score) = ast else {
+            panic!("not a score");
+        };
+        assert_eq!(score.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_superscript() -> Result<()> {
+        let importer = RechordImporter::default();
+        assert_eq!(importer.import("C⁷ Cm⁹\n")?, importer.import("C7 Cm9\n")?);
+        assert_eq!(importer.import("G₁₃\n")?, importer.import("G13\n")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lenient() -> Result<()> {
+        let strict = RechordImporter::default();
+        let lenient = RechordImporter {
+            lenient: true,
+            ..Default::default()
+        };
+        assert!(strict.import("cmaj7\n").is_err());
+        assert_eq!(lenient.import("cmaj7\n")?, strict.import("Cmaj7\n")?);
+        assert_eq!(lenient.import("CMAJ7\n")?, strict.import("Cmaj7\n")?);
+        assert_eq!(lenient.import("am7\n")?, strict.import("Am7\n")?);
+        assert_eq!(lenient.import("bb7/d\n")?, strict.import("Bb7/D\n")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lenient_flat_degrees() -> Result<()> {
+        let strict = RechordImporter::default();
+        let lenient = RechordImporter {
+            lenient: true,
+            ..Default::default()
+        };
+        for code in ["bVII IV\n", "I bIII | bVII\n", "[C] bVII/bIII\n"] {
+            assert_eq!(lenient.import(code)?, strict.import(code)?, "{}", code);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_lenient_markers() -> Result<()> {
+        let lenient = RechordImporter {
+            lenient: true,
+            ..Default::default()
+        };
+        let ast = lenient.import("[Verse Minor]\ncmin7 gSUS4 | fMAJ7\n")?;
+        let expected = RechordImporter::default().import("[Verse Minor]\nCm7 Gsus4 | Fmaj7\n")?;
+        assert_eq!(ast, expected);
+        let Ast::Score(score) = &ast else { panic!() };
+        assert_eq!(*score[0], Ast::Marker("Verse Minor".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_chord_per_beat() -> Result<()> {
+        let strict = RechordImporter {
+            chord_per_beat: true,
+            ..Default::default()
+        };
+        let e = strict.import("C G Am F | C G Am\n").unwrap_err();
+        assert_eq!(e.to_string(), "measure 2: 3 beats, expected 4");
+        assert!(strict.import("C = G = | F G C C & C = = =\n").is_ok());
+        assert!(strict.import("{3} C G Am\n").is_ok());
+        // padded by default
+        assert!(RechordImporter::default().import("C G Am\n").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_two_bar_repeat() -> Result<()> {
+        let importer = RechordImporter::default();
+        assert_eq!(
+            importer.import("C | G | %%\n")?,
+            importer.import("C | G | C | G\n")?
+        );
+        assert_eq!(
+            importer.import("C | Am7 =\n[A]\nF | %% | G\n")?.to_string(),
+            importer
+                .import("C | Am7 =\n[A]\nF | Am7 = | F | G\n")?
+                .to_string()
+        );
+        assert_eq!(
+            importer.import("C | %%\n").unwrap_err().to_string(),
+            "%% needs two bars before it"
+        );
+        Ok(())
+    }
 }
@@ -9,7 +9,7 @@ use crate::model::{
     scale::Scale,
 };
 use anyhow::Result;
-use std::{collections::BTreeSet, str::FromStr};
+use std::str::FromStr;
 use symbolic_expressions::{parser::parse_str, Sexp};
 
 impl super::Importer for SexpImporter {
@@ -24,7 +24,7 @@ fn degree_from_str(s: &str) -> Result<u8> {
         s if s.ends_with("b") | s.ends_with("#") => {
             let d = parser_roman_num(&s[..s.len() - 1])?;
             let a = Accidental::from_str(&s[s.len() - 1..])?;
-            Ok((Scale::Major.semitone(d) as i8 + a as i8) as u8)
+            Ok(((Scale::Major.semitone(d) as i8 + i8::from(a) + 12) % 12) as u8)
         }
         s => {
             let d = parser_roman_num(s)?;
@@ -35,8 +35,8 @@ fn degree_from_str(s: &str) -> Result<u8> {
 
 fn parse_key(key: &str) -> Result<Key> {
     match key {
-        pitch if PITCH_REGEX.is_match(&pitch) => Ok(Key::Absolute(Pitch::from_str(pitch)?)),
-        degree if DEGREE_REGEX.is_match(&degree) => Ok(Key::Relative(degree_from_str(degree)?)),
+        pitch if PITCH_REGEX.is_match(pitch) => Ok(Key::Absolute(Pitch::from_str(pitch)?)),
+        degree if DEGREE_REGEX.is_match(degree) => Ok(Key::Relative(degree_from_str(degree)?)),
         _ => Err(anyhow::anyhow!("invalid key: {}", key)),
     }
 }
@@ -47,16 +47,12 @@ fn parse_node(sexp: &Sexp) -> Result<Node> {
         Sexp::String(s) if s == "=" => Ok(Node::Sustain),
         Sexp::String(s) if s == "_" => Ok(Node::Rest),
         Sexp::String(s) if s == "%" => Ok(Node::Repeat),
-        Sexp::String(key) => Ok(Node::Chord(ChordNode {
-            key: parse_key(&key)?,
-            modifiers: BTreeSet::new(),
-            on: None,
-        })),
-        Sexp::List(list) if starts_with(&sexp, "chord") => Ok(Node::Chord(ChordNode {
-            key: parse_key(list[1].string()?)?,
-            modifiers: BTreeSet::new(),
-            on: None,
-        })),
+        Sexp::String(s) if s == "<" => Ok(Node::Crescendo),
+        Sexp::String(s) if s == ">" => Ok(Node::Decrescendo),
+        Sexp::String(key) => Ok(Node::Chord(ChordNode::new(parse_key(key)?))),
+        Sexp::List(list) if starts_with(sexp, "chord") => {
+            Ok(Node::Chord(ChordNode::new(parse_key(list[1].string()?)?)))
+        }
         _ => Err(anyhow::anyhow!("unexpected input: {:?}", sexp)),
     }
 }
@@ -68,7 +64,7 @@ fn starts_with(sexp: &Sexp, tag: &str) -> bool {
 fn parse_ast(sexp: &Sexp) -> Result<Ast> {
     match sexp {
         // score(...: Measure) -> Score
-        Sexp::List(list) if starts_with(&sexp, "score") => {
+        Sexp::List(list) if starts_with(sexp, "score") => {
             let measures = list[1..]
                 .iter()
                 .map(|ast| parse_ast(ast).map(Box::new))
@@ -86,9 +82,7 @@ fn parse_ast(sexp: &Sexp) -> Result<Ast> {
         }
         // (...: Node): Measure
         Sexp::List(list) => Ok(Ast::Measure(
-            list.into_iter()
-                .map(parse_node)
-                .collect::<Result<Vec<_>>>()?,
+            list.iter().map(parse_node).collect::<Result<Vec<_>>>()?,
             false,
         )),
         _ => Err(anyhow::anyhow!("unexpected input: {:?}", sexp)),
@@ -133,4 +127,13 @@ mod tests {
         assert_eq!(score, SexpImporter.import("(score (C F) (D G))")?);
         Ok(())
     }
+
+    #[test]
+    fn test_enharmonic() -> Result<()> {
+        assert_eq!(
+            SexpImporter.import("(score (Cb Fb E# B#))")?,
+            SexpImporter.import("(score (B E F C))")?
+        );
+        Ok(())
+    }
 }
@@ -2,25 +2,204 @@ use crate::model::{
     chord::{match_pitches, Chord},
     key::Key,
     modifier::Modifier,
-    pitch::Pitch,
+    pitch::{midi_note, Pitch},
 };
 use anyhow::Result;
 use std::collections::BTreeSet;
 
-#[derive(Debug, PartialEq)]
+/// sixteenths in a measure
+pub const MEASURE_LENGTH: u32 = 16;
+
+/// returns the length in sixteenths of each node in a measure of `n` timed nodes
+pub fn measure_unit_size(n: usize) -> Result<u32> {
+    let len = match n {
+        1 => 1,
+        2 => 2,
+        3..=4 => 4,
+        5..=8 => 8,
+        9..=16 => 16,
+        _ => {
+            return Err(anyhow::anyhow!("too many nodes: {}", n));
+        }
+    };
+    Ok(MEASURE_LENGTH / len)
+}
+
+/// splits nodes of a measure at `&`, the first voice comes first
+pub fn voices(nodes: &[Node]) -> Vec<&[Node]> {
+    nodes.split(|n| *n == Node::Voice).collect()
+}
+
+/// returns how many equal slots a measure is split into, one per timed node and a tuplet's width.
+/// a leading `{n}` forces `n` slots, otherwise it follows `measure_unit_size`
+pub fn measure_slots(nodes: &[Node]) -> Result<u32> {
+    let timed = nodes.iter().map(|n| n.width()).sum::<usize>();
+    match nodes.first() {
+        Some(Node::Subdivision(0)) => Err(anyhow::anyhow!("empty subdivision")),
+        Some(Node::Subdivision(n)) if timed > *n as usize => Err(anyhow::anyhow!(
+            "{} nodes in a measure of {} subdivisions",
+            timed,
+            n
+        )),
+        Some(Node::Subdivision(n)) => Ok(*n as u32),
+        _ => Ok(MEASURE_LENGTH / measure_unit_size(timed)?),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Ast {
     Comment(String),
+    // `[Verse]`: section label
+    Marker(String),
+    // `@...`: applies from the following measure
+    Directive(Directive),
     // nodes, br?
     Measure(Vec<Node>, bool),
     Score(Vec<Box<Ast>>),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Directive {
+    // `@dynamics cresc 60 100 4`: velocity ramps linearly from 60 to 100 over 4 measures
+    Dynamics { from: u8, to: u8, measures: u32 },
+    // `@bars 32`: the chart must have exactly 32 measures
+    Bars(usize),
+    // `@octave -1`: shifts following chords by octaves, adding up with earlier shifts
+    Octave(i8),
+    // `@tempo h=60`: `per_minute` notes of `beat` sixteenths, a half note here
+    Tempo { beat: u32, per_minute: f32 },
+    // `@vel 90` sets the velocity of following chords, `@vel reset` (None) returns to the default
+    Velocity(Option<u8>),
+}
+
+impl Directive {
+    /// returns the tempo of `@tempo` in quarter notes per minute
+    pub fn quarters_per_minute(&self) -> Option<f32> {
+        match self {
+            Directive::Tempo { beat, per_minute } => Some(per_minute * *beat as f32 / 4.0),
+            _ => None,
+        }
+    }
+}
+
+impl IntoIterator for Ast {
+    type Item = Node;
+    type IntoIter = std::vec::IntoIter<Node>;
+
+    /// yields nodes of all measures in order, skipping comments and markers and opening tuplets
+    fn into_iter(self) -> Self::IntoIter {
+        fn collect(ast: Ast, nodes: &mut Vec<Node>) {
+            match ast {
+                Ast::Score(score) => score.into_iter().for_each(|ast| collect(*ast, nodes)),
+                Ast::Measure(measure, _) => {
+                    for node in measure {
+                        match node {
+                            Node::Tuplet(tuplet) => nodes.extend(tuplet),
+                            node => nodes.push(node),
+                        }
+                    }
+                }
+                Ast::Comment(_) | Ast::Marker(_) | Ast::Directive(_) => {}
+            }
+        }
+        let mut nodes = vec![];
+        collect(self, &mut nodes);
+        nodes.into_iter()
+    }
+}
+
+impl Ast {
+    /// groups measures under the label of the marker preceding them.
+    /// measures before the first marker are labeled `""`
+    pub fn sections(&self) -> Vec<(String, Vec<&Ast>)> {
+        fn collect<'a>(ast: &'a Ast, sections: &mut Vec<(String, Vec<&'a Ast>)>) {
+            match ast {
+                Ast::Score(score) => score.iter().for_each(|ast| collect(ast, sections)),
+                Ast::Marker(label) => sections.push((label.clone(), vec![])),
+                Ast::Measure(_, _) => match sections.last_mut() {
+                    Some((_, measures)) => measures.push(ast),
+                    None => sections.push((String::new(), vec![ast])),
+                },
+                Ast::Comment(_) | Ast::Directive(_) => {}
+            }
+        }
+        let mut sections = vec![];
+        collect(self, &mut sections);
+        sections
+    }
+
+    /// returns (beat offset in quarter notes, node) for each measure.
+    /// hairpins share the beat of the node following them
+    pub fn beats(&self) -> Result<Vec<Vec<(f64, &Node)>>> {
+        match self {
+            Ast::Score(score) => Ok(score
+                .iter()
+                .map(|ast| ast.beats())
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .flatten()
+                .collect()),
+            Ast::Measure(nodes, _) => {
+                let nodes = voices(nodes)[0];
+                let slots = measure_slots(nodes)?;
+                let mut slot = 0;
+                let mut beats = vec![];
+                let beat = |slot: f64| 4.0 * slot / slots as f64;
+                for node in nodes {
+                    match node {
+                        Node::Tuplet(tuplet) => {
+                            let step = node.width() as f64 / tuplet.len() as f64;
+                            for (i, node) in tuplet.iter().enumerate() {
+                                beats.push((beat(slot as f64 + i as f64 * step), node));
+                            }
+                        }
+                        node => beats.push((beat(slot as f64), node)),
+                    }
+                    slot += node.width();
+                }
+                Ok(vec![beats])
+            }
+            Ast::Comment(_) | Ast::Marker(_) | Ast::Directive(_) => Ok(vec![]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Node {
     Chord(ChordNode),
     Rest,
     Sustain,
     Repeat,
+    // `<`: following chords of the measure get louder
+    Crescendo,
+    // `>`: following chords of the measure get softer
+    Decrescendo,
+    // `{3}`: splits the measure into this many equal slots
+    Subdivision(u8),
+    // `&`: following nodes form another voice sounding along with the measure
+    Voice,
+    // `(C G Am)3`: the nodes share the slots of `Node::width`, a triplet in two beats
+    Tuplet(Vec<Node>),
+}
+
+impl Node {
+    /// returns whether the node occupies a beat in its measure
+    pub fn is_timed(&self) -> bool {
+        !matches!(
+            self,
+            Node::Crescendo | Node::Decrescendo | Node::Subdivision(_) | Node::Voice
+        )
+    }
+
+    /// returns how many slots of its measure the node occupies,
+    /// a tuplet of n takes as many slots as the largest power of two below n
+    pub fn width(&self) -> usize {
+        match self {
+            Node::Tuplet(nodes) => 1 << (nodes.len().max(2) - 1).ilog2(),
+            node if node.is_timed() => 1,
+            _ => 0,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -28,34 +207,157 @@ pub struct ChordNode {
     pub key: Key,
     pub modifiers: BTreeSet<Modifier>,
     pub on: Option<Key>,
+    // `<C>G`: grace chord played briefly before this chord
+    pub grace: Option<Box<ChordNode>>,
+    // `>G`: arrives an eighth before its beat, cutting the previous note short
+    pub push: bool,
+    // `C*`: rings on through the rests that follow
+    pub ring: bool,
+    // `C.`: staccato, sounds for half its length and rests for the other half
+    pub staccato: bool,
+    // `C(Am)`: optional substitute, played instead with `MidiExporter::alternates`
+    pub alternate: Option<Box<ChordNode>>,
 }
 
 impl ChordNode {
-    pub fn absolute(pitch: Pitch) -> Self {
+    pub fn new(key: Key) -> Self {
         ChordNode {
-            key: Key::Absolute(pitch),
+            key,
             modifiers: BTreeSet::new(),
             on: None,
+            grace: None,
+            push: false,
+            ring: false,
+            staccato: false,
+            alternate: None,
         }
     }
 
+    pub fn absolute(pitch: Pitch) -> Self {
+        Self::new(Key::Absolute(pitch))
+    }
+
     pub fn relative(semitone: u8) -> Self {
-        ChordNode {
-            key: Key::Relative(semitone),
-            modifiers: BTreeSet::new(),
-            on: None,
+        Self::new(Key::Relative(semitone))
+    }
+
+    /// returns inversion implied by the on-chord (0: root, 1: third, ...), None if the bass isn't a chord tone
+    pub fn inversion(&self) -> Result<Option<u8>> {
+        let bass = match (&self.key, &self.on) {
+            (_, None) => return Ok(Some(0)),
+            (Key::Absolute(key), Some(Key::Absolute(on))) => on.diff(key),
+            (Key::Relative(key), Some(Key::Relative(on))) => (on + 12 - key) % 12,
+            _ => return Err(anyhow::anyhow!("key type mismatch")),
+        };
+        Ok(self
+            .semitones()?
+            .iter()
+            .position(|s| s % 12 == bass)
+            .map(|i| i as u8))
+    }
+
+    /// returns the modifiers in the order of `Modifier::phase`
+    pub fn ordered_modifiers(&self) -> Vec<&Modifier> {
+        let mut modifiers = self.modifiers.iter().collect::<Vec<_>>();
+        modifiers.sort_by_key(|m| m.phase());
+        modifiers
+    }
+
+    /// returns semitones of the chord tones from the root
+    pub fn semitones(&self) -> Result<BTreeSet<u8>> {
+        let mut chord = Chord::new(0, 0, self.key.clone());
+        for modifier in self.ordered_modifiers() {
+            chord.modify(modifier)?;
         }
+        Ok(chord.semitones)
     }
 
     pub fn to_chord(&self) -> Result<Chord> {
         let mut chord = Chord::new(5, 0, self.key.clone());
         chord.on = self.on.clone();
-        for modifier in &self.modifiers {
+        for modifier in self.ordered_modifiers() {
             chord.modify(modifier)?;
         }
-        let (octave, inversion) = match_pitches(12 * chord.octave, &chord)?;
+        // voiced around middle C
+        let (octave, inversion) = match_pitches(midi_note(4, 0), &chord)?;
         chord.octave = octave;
         chord.inversion = inversion;
         Ok(chord)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Ast, ChordNode, Node};
+    use crate::import::{Importer, RechordImporter};
+    use crate::model::{key::Key, modifier::Modifier, pitch::Pitch};
+    use anyhow::Result;
+
+    #[test]
+    fn test_into_iter() {
+        let c = || Node::Chord(ChordNode::absolute(Pitch::C));
+        let g = || Node::Chord(ChordNode::absolute(Pitch::G));
+        let ast = Ast::Score(vec![
+            Box::new(Ast::Comment("intro".to_string())),
+            Box::new(Ast::Measure(vec![c(), g()], false)),
+            Box::new(Ast::Measure(vec![g(), Node::Sustain], true)),
+        ]);
+        assert_eq!(
+            ast.into_iter().collect::<Vec<_>>(),
+            vec![c(), g(), g(), Node::Sustain]
+        );
+    }
+
+    #[test]
+    fn test_sections() -> Result<()> {
+        let ast = RechordImporter::default().import("[Verse]\nC | G\n[Chorus]\nAm | F | C\n")?;
+        let sections = ast
+            .sections()
+            .into_iter()
+            .map(|(label, measures)| (label, measures.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sections,
+            vec![("Verse".to_string(), 2), ("Chorus".to_string(), 3)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_beats() -> Result<()> {
+        let c = Node::Chord(ChordNode::absolute(Pitch::C));
+        let ast = Ast::Score(vec![
+            Box::new(Ast::Measure(
+                vec![c.clone(), Node::Sustain, c.clone(), Node::Rest],
+                false,
+            )),
+            Box::new(Ast::Measure(
+                vec![c.clone(), Node::Crescendo, c.clone()],
+                false,
+            )),
+        ]);
+        let beats = ast
+            .beats()?
+            .into_iter()
+            .map(|m| m.into_iter().map(|(beat, _)| beat).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(beats, vec![vec![0.0, 1.0, 2.0, 3.0], vec![0.0, 2.0, 2.0]]);
+
+        let ast = Ast::Measure(vec![Node::Subdivision(3), c.clone(), c.clone()], false);
+        let beats = ast.beats()?[0].iter().map(|(b, _)| *b).collect::<Vec<_>>();
+        assert_eq!(beats, vec![0.0, 0.0, 4.0 / 3.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_inversion() -> Result<()> {
+        let mut chord = ChordNode::absolute(Pitch::C);
+        chord.modifiers.insert(Modifier::Major(5));
+        assert_eq!(chord.inversion()?, Some(0));
+        for (on, inversion) in [(Pitch::E, Some(1)), (Pitch::G, Some(2)), (Pitch::D, None)] {
+            chord.on = Some(Key::Absolute(on));
+            assert_eq!(chord.inversion()?, inversion);
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,17 @@
 use crate::model::{
     key::Key,
     modifier::Modifier,
-    scale::{Degree, Scale},
+    pitch::{midi_note, Pitch},
+    scale::Scale,
 };
 use anyhow::Result;
-use std::{collections::BTreeSet, fmt::Debug};
+use serde::Serialize;
+use std::{
+    collections::BTreeSet,
+    fmt::{Debug, Display},
+};
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Chord {
     pub octave: u8,
     pub inversion: u8,
@@ -15,6 +20,29 @@ pub struct Chord {
     pub on: Option<Key>,
 }
 
+/// qualities `quantize_to_nearest_quality` snaps to
+const QUALITIES: [&[Modifier]; 9] = [
+    &[Modifier::Major(5)],
+    &[Modifier::Major(5), Modifier::Minor(5)],
+    &[Modifier::Major(5), Modifier::Dominant(7)],
+    &[Modifier::Major(5), Modifier::Major(7)],
+    &[Modifier::Major(5), Modifier::Minor(7)],
+    &[Modifier::Major(5), Modifier::MinorMajaor7],
+    &[Modifier::Major(5), Modifier::Dim],
+    &[Modifier::Major(5), Modifier::Aug],
+    &[Modifier::Major(5), Modifier::Sus4],
+];
+
+/// summary of a single chord for the web chord explorer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChordInfo {
+    pub notes: Vec<String>,
+    pub pitch_classes: Vec<u8>,
+    /// semitones above the root
+    pub intervals: Vec<u8>,
+    pub scale: Option<String>,
+}
+
 /// returns best octave and inversion to base pitch
 pub fn match_pitches(base: u8, chord: &Chord) -> Result<(u8, u8)> {
     let (mut diff, mut best_octave, mut best_inversion) = (u8::MAX, 0, 0);
@@ -23,7 +51,7 @@ pub fn match_pitches(base: u8, chord: &Chord) -> Result<(u8, u8)> {
         for inversion in 0..chord.semitones.len() as u8 {
             chord.octave = octave;
             chord.inversion = inversion;
-            let chord_root = chord.root_pitch().unwrap();
+            let chord_root = chord.root_pitch()?;
             let d = base.abs_diff(chord_root);
             if d < diff {
                 diff = d;
@@ -49,6 +77,20 @@ impl Debug for Chord {
     }
 }
 
+/// renders the nearest quality, `Cm7` or `Am/G`
+impl Display for Chord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.key)?;
+        for modifier in self.nearest_quality().1 {
+            write!(f, "{}", modifier)?;
+        }
+        if let Some(on) = &self.on {
+            write!(f, "/{}", on)?;
+        }
+        Ok(())
+    }
+}
+
 impl Chord {
     pub fn new(octave: u8, inversion: u8, key: Key) -> Self {
         Self {
@@ -87,10 +129,34 @@ impl Chord {
             Modifier::Major(6) => modify_degree(self, Scale::Major, &[1, 3, 5, 6]),
             Modifier::Major(7) => modify_degree(self, Scale::Major, &[1, 3, 5, 7]),
             Modifier::Major(9) => modify_degree(self, Scale::Major, &[1, 3, 5, 7, 9]),
+            Modifier::Major(11) => modify_degree(self, Scale::Major, &[1, 3, 5, 7, 9, 11]),
+            // the 11th clashes with the major third and is left out
+            Modifier::Major(13) => modify_degree(self, Scale::Major, &[1, 3, 5, 7, 9, 13]),
             Modifier::Minor(5) => modify_degree(self, Scale::Minor, &[1, 3, 5]),
-            Modifier::Minor(6) => modify_degree(self, Scale::Minor, &[1, 3, 5, 6]),
+            // the sixth of a minor sixth chord is major, as in the dorian mode
+            Modifier::Minor(6) => {
+                modify_degree(self, Scale::Minor, &[1, 3, 5])?;
+                modify_degree(self, Scale::Major, &[6])
+            }
             Modifier::Minor(7) => modify_degree(self, Scale::Minor, &[1, 3, 5, 7]),
             Modifier::Minor(9) => modify_degree(self, Scale::Minor, &[1, 3, 5, 7, 9]),
+            Modifier::Dominant(7) => {
+                modify_degree(self, Scale::Major, &[1, 3, 5])?;
+                modify_degree(self, Scale::Minor, &[7])
+            }
+            Modifier::Dominant(9) => {
+                modify_degree(self, Scale::Major, &[1, 3, 5, 9])?;
+                modify_degree(self, Scale::Minor, &[7])
+            }
+            Modifier::Dominant(11) => {
+                modify_degree(self, Scale::Major, &[1, 3, 5, 9, 11])?;
+                modify_degree(self, Scale::Minor, &[7])
+            }
+            // the 11th is left out as in the major thirteenth
+            Modifier::Dominant(13) => {
+                modify_degree(self, Scale::Major, &[1, 3, 5, 9, 13])?;
+                modify_degree(self, Scale::Minor, &[7])
+            }
             Modifier::MinorMajaor7 => {
                 for (s, d) in [
                     (Scale::Minor, 1),
@@ -105,7 +171,7 @@ impl Chord {
             }
             Modifier::Sus2 => {
                 self.semitones.remove(&self.scale().semitone(3));
-                self.semitones.insert(Scale::Major.semitone(3) - 1);
+                self.semitones.insert(Scale::Major.semitone(2));
                 Ok(())
             }
             Modifier::Sus4 => {
@@ -113,6 +179,10 @@ impl Chord {
                 self.semitones.insert(Scale::Major.semitone(3) + 1);
                 Ok(())
             }
+            Modifier::Power => {
+                self.semitones.remove(&self.scale().semitone(3));
+                Ok(())
+            }
             Modifier::Flat5th => {
                 self.semitones.remove(&self.scale().semitone(5));
                 self.semitones.insert(Scale::Major.semitone(5) - 1);
@@ -126,8 +196,9 @@ impl Chord {
             Modifier::Aug7 => {
                 self.semitones.remove(&self.scale().semitone(5));
                 self.semitones.insert(Scale::Major.semitone(5) + 1);
-                self.semitones.remove(&self.scale().semitone(7));
-                self.semitones.insert(Scale::Major.semitone(7) + 1);
+                // the seventh is minor, whatever the quality put there
+                self.semitones.remove(&Scale::Major.semitone(7));
+                self.semitones.insert(Scale::Minor.semitone(7));
                 Ok(())
             }
             Modifier::Dim => {
@@ -142,21 +213,18 @@ impl Chord {
                 self.semitones.insert(Scale::Major.semitone(3) - 1);
                 self.semitones.remove(&self.scale().semitone(5));
                 self.semitones.insert(Scale::Major.semitone(5) - 1);
-                self.semitones.remove(&self.scale().semitone(7));
-                self.semitones.insert(Scale::Major.semitone(7) - 1);
+                // a diminished seventh below the octave, not the minor one of m7b5
+                self.semitones.remove(&Scale::Major.semitone(7));
+                self.semitones.remove(&Scale::Minor.semitone(7));
+                self.semitones.insert(Scale::Major.semitone(7) - 2);
                 Ok(())
             }
             Modifier::Omit(d) => {
-                self.semitones.remove(&self.scale().semitone(*d));
-                Ok(())
-            }
-            Modifier::Add(d) => {
-                self.semitones.insert(self.scale().semitone(*d));
+                self.semitones.remove(&d.semitone(&self.scale()));
                 Ok(())
             }
-            Modifier::Tension(Degree(d, a)) => {
-                self.semitones
-                    .insert(self.scale().semitone(*d) + a.clone() as u8);
+            Modifier::Add(d) | Modifier::Tension(d) => {
+                self.semitones.insert(d.semitone(&self.scale()));
                 Ok(())
             }
             _ => Err(anyhow::anyhow!("unknown mod: {:?}", modifier)),
@@ -166,22 +234,267 @@ impl Chord {
     pub fn root_pitch(&self) -> Result<u8> {
         let s = *self.semitones.iter().nth(self.inversion as usize).unwrap();
         match &self.key {
-            Key::Absolute(p) => Ok(12 + 12 * self.octave + p.clone() as u8 + s - 1),
+            Key::Absolute(p) => Ok(midi_note(self.octave, *p as u8 + s)),
             Key::Relative(d) => Err(anyhow::anyhow!("relative key: {}", d)),
         }
     }
 
+    /// returns MIDI note number of the nominal root (bottom of root-position voicing), ignoring inversion
+    pub fn root_note_number(&self) -> Result<u8> {
+        match &self.key {
+            Key::Absolute(p) => Ok(midi_note(self.octave, *p as u8)),
+            Key::Relative(d) => Err(anyhow::anyhow!("relative key: {}", d)),
+        }
+    }
+
+    /// returns absolute pitch classes of the chord tones (without on-chord bass)
+    pub fn pitch_classes(&self) -> Result<BTreeSet<Pitch>> {
+        let Key::Absolute(p) = &self.key else {
+            return Err(anyhow::anyhow!("relative key: {}", self.key));
+        };
+        self.semitones
+            .iter()
+            .map(|s| Pitch::try_from((*p as u8 + s) % 12))
+            .collect()
+    }
+
+    /// returns pitch classes (0 for C) both chords sound, degree chords are compared by degree.
+    /// a degree chord shares nothing with a pitch chord
+    pub fn common_tones(&self, other: &Chord) -> Vec<u8> {
+        let classes = |chord: &Chord, root: u8| -> BTreeSet<u8> {
+            chord.semitones.iter().map(|s| (root + s) % 12).collect()
+        };
+        let (a, b) = match (&self.key, &other.key) {
+            (Key::Absolute(a), Key::Absolute(b)) => (*a as u8, *b as u8),
+            (Key::Relative(a), Key::Relative(b)) => (*a, *b),
+            _ => return vec![],
+        };
+        classes(self, a)
+            .intersection(&classes(other, b))
+            .copied()
+            .collect()
+    }
+
+    /// returns whether all chord tones belong to `scale` on `key`, degree chords taken in `key`
+    pub fn is_diatonic_to(&self, key: Pitch, scale: Scale) -> bool {
+        let tones = scale
+            .semitones(&[1, 2, 3, 4, 5, 6, 7])
+            .into_iter()
+            .map(|s| Pitch::try_from((key as u8 + s) % 12))
+            .collect::<Result<BTreeSet<_>>>();
+        let chord = Chord {
+            key: self.key.clone().into_pitch(key),
+            ..self.clone()
+        };
+        match (tones, chord.pitch_classes()) {
+            (Ok(tones), Ok(pitches)) => pitches.is_subset(&tones),
+            _ => false,
+        }
+    }
+
+    /// returns a mode to improvise over the chord by its quality, None for ambiguous ones (sus, aug)
+    pub fn suggested_scale(&self) -> Option<Scale> {
+        let has = |s: u8| self.semitones.iter().any(|t| t % 12 == s);
+        match (has(3), has(4)) {
+            (false, true) if has(11) && has(6) => Some(Scale::Lydian),
+            (false, true) if has(10) => Some(Scale::Mixolydian),
+            (false, true) if !has(8) => Some(Scale::Major),
+            (true, false) if has(6) => Some(Scale::Locrian),
+            (true, false) if has(1) => Some(Scale::Phrygian),
+            (true, false) if has(10) => Some(Scale::Dorian),
+            (true, false) => Some(Scale::Minor),
+            _ => None,
+        }
+    }
+
+    /// returns the quality suffix written after a roman numeral in analysis,
+    /// `` for major, `m`, `°`, `+`, `7`, `maj7` and for other sevenths `m7`, `ø7`, `°7`
+    pub fn roman_quality_suffix(&self) -> &'static str {
+        let has = |s: u8| self.semitones.iter().any(|t| t % 12 == s);
+        match (has(3), has(4), has(6), has(7), has(8)) {
+            (true, false, true, false, _) if has(9) => "°7",
+            (true, false, true, false, _) if has(10) => "ø7",
+            (true, false, true, false, _) => "°",
+            (false, true, false, false, true) => "+",
+            (false, true, _, _, _) if has(10) => "7",
+            (false, true, _, _, _) if has(11) => "maj7",
+            (true, false, _, _, _) if has(10) => "m7",
+            (true, false, _, _, _) => "m",
+            _ => "",
+        }
+    }
+
+    /// returns the chord raised by `semitones`
+    pub fn transpose(&self, semitones: u8) -> Chord {
+        Chord {
+            key: self.key.transpose(semitones),
+            on: self.on.as_ref().map(|on| on.transpose(semitones)),
+            ..self.clone()
+        }
+    }
+
+    /// returns common reharmonizations: tritone substitute of a dominant,
+    /// relative minor/major and the diminished seventh a semitone above
+    pub fn substitutions(&self) -> Vec<Chord> {
+        let with_semitones = |semitones: u8, tones: &[u8]| Chord {
+            semitones: BTreeSet::from_iter(tones.iter().copied()),
+            on: None,
+            ..self.transpose(semitones)
+        };
+        let has = |s: u8| self.semitones.contains(&s);
+        let mut subs = vec![];
+        if has(4) && has(10) {
+            subs.push(Chord {
+                on: None,
+                ..self.transpose(6)
+            });
+        }
+        if has(4) && has(7) && !has(10) {
+            let tones: &[u8] = if has(11) { &[0, 3, 7, 10] } else { &[0, 3, 7] };
+            subs.push(with_semitones(9, tones));
+        }
+        if has(3) && has(7) {
+            let tones: &[u8] = if has(10) { &[0, 4, 7, 11] } else { &[0, 4, 7] };
+            subs.push(with_semitones(3, tones));
+        }
+        subs.push(with_semitones(1, &[0, 3, 6, 9]));
+        subs
+    }
+
+    /// returns the recognized quality nearest to note numbers, trying each note as the root.
+    /// a bass other than the root becomes the on-chord
+    pub fn quantize_to_nearest_quality(notes: &[u8]) -> Result<Chord> {
+        let bass = *notes
+            .iter()
+            .min()
+            .ok_or_else(|| anyhow::anyhow!("no notes"))?;
+        let mut roots = notes.to_vec();
+        roots.sort();
+        let mut best: Option<(usize, Chord)> = None;
+        for root in roots {
+            let key = Key::Absolute(Pitch::try_from(root % 12)?);
+            let played = Chord {
+                semitones: notes.iter().map(|n| (n + 12 - root % 12) % 12).collect(),
+                ..Chord::new(0, 0, key.clone())
+            };
+            let (d, quality) = played.nearest_quality();
+            if best.as_ref().is_none_or(|(best, _)| d < *best) {
+                let mut chord = Chord::new(0, 0, key);
+                for modifier in quality {
+                    chord.modify(modifier)?;
+                }
+                best = Some((d, chord));
+            }
+        }
+        let (_, mut chord) = best.unwrap();
+        let Key::Absolute(root) = chord.key else {
+            unreachable!()
+        };
+        let lowest = notes
+            .iter()
+            .filter(|n| *n % 12 == root as u8)
+            .min()
+            .unwrap();
+        chord.octave = (lowest / 12).saturating_sub(1);
+        if bass % 12 != root as u8 {
+            chord.on = Some(Key::Absolute(Pitch::try_from(bass % 12)?));
+        }
+        Ok(chord)
+    }
+
+    /// returns the entry of `QUALITIES` closest to the semitones, and its distance
+    fn nearest_quality(&self) -> (usize, &'static [Modifier]) {
+        QUALITIES
+            .iter()
+            .map(|quality| {
+                let mut chord = Chord::new(self.octave, 0, self.key.clone());
+                for modifier in *quality {
+                    chord.modify(modifier).unwrap();
+                }
+                let d = self.distance(&chord).unwrap()
+                    + self.semitones.len().abs_diff(chord.semitones.len());
+                (d, *quality)
+            })
+            .min_by_key(|(d, _)| *d)
+            .unwrap()
+    }
+
+    /// returns note names, pitch-class set, intervals and suggested scale
+    pub fn info(&self) -> Result<ChordInfo> {
+        let Key::Absolute(root) = &self.key else {
+            return Err(anyhow::anyhow!("relative key: {}", self.key));
+        };
+        let notes = self
+            .semitones
+            .iter()
+            .map(|s| Pitch::try_from((*root as u8 + s) % 12).map(|p| p.to_string()))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ChordInfo {
+            notes,
+            pitch_classes: self.pitch_classes()?.into_iter().map(|p| p as u8).collect(),
+            intervals: self.semitones.iter().copied().collect(),
+            scale: self.suggested_scale().map(|s| format!("{:?}", s)),
+        })
+    }
+
+    /// returns the chord in root position and each inversion, one per chord tone
+    pub fn inversions(&self) -> Vec<Chord> {
+        (0..self.semitones.len() as u8)
+            .map(|inversion| Chord {
+                inversion,
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    /// returns the voicing with the second voice from the top lowered by an octave
+    pub fn drop2(&self) -> Chord {
+        self.drop(2)
+    }
+
+    /// returns the voicing with the third voice from the top lowered by an octave
+    pub fn drop3(&self) -> Chord {
+        self.drop(3)
+    }
+
+    /// lowers the `n`th voice from the top of the inverted voicing, chords under 4 tones are kept.
+    /// semitones stay non-negative by moving the chord an octave down
+    fn drop(&self, n: usize) -> Chord {
+        if self.semitones.len() < 4 {
+            return self.clone();
+        }
+        let mut tones = self.semitones.iter().map(|s| *s as i16).collect::<Vec<_>>();
+        for _ in 0..self.inversion {
+            let tone = tones.remove(0);
+            tones.push(tone + 12);
+        }
+        tones.sort();
+        let i = tones.len() - n;
+        tones[i] -= 12;
+        let (octave, shift) = match tones.iter().min() {
+            Some(lowest) if *lowest < 0 && self.octave > 0 => (self.octave - 1, 12),
+            Some(lowest) if *lowest < 0 => return self.clone(),
+            _ => (self.octave, 0),
+        };
+        Chord {
+            octave,
+            inversion: 0,
+            semitones: tones.into_iter().map(|t| (t + shift) as u8).collect(),
+            ..self.clone()
+        }
+    }
+
     /// returns edit distance of each semitone
     pub fn distance(&self, other: &Self) -> Result<usize> {
         let key_dist = match (&self.key, &other.key) {
             (Key::Absolute(ap), Key::Absolute(bp)) => {
-                let a = self.octave * 12 + ap.clone() as u8;
-                let b = other.octave * 12 + bp.clone() as u8;
+                let a = midi_note(self.octave, *ap as u8);
+                let b = midi_note(other.octave, *bp as u8);
                 Ok::<_, anyhow::Error>(a.abs_diff(b))
             }
             (Key::Relative(sa), Key::Relative(sb)) => {
-                let a = self.octave * 12 + *sa;
-                let b = other.octave * 12 + *sb;
+                let a = midi_note(self.octave, *sa);
+                let b = midi_note(other.octave, *sb);
                 Ok(a.abs_diff(b))
             }
             _ => return Err(anyhow::anyhow!("key type mismatch")),
@@ -198,11 +511,19 @@ impl Chord {
 
 #[cfg(test)]
 mod tests {
-    use crate::model::{key::Key, modifier::Modifier, pitch::Pitch};
+    use crate::export::{Exporter, MidiExporter};
+    use crate::import::{first_chord, Importer, RechordImporter};
+    use crate::model::{
+        ast::{ChordNode, Node},
+        key::Key,
+        modifier::Modifier,
+        pitch::{Accidental, Pitch},
+        scale::{Degree, Scale},
+    };
 
-    use super::Chord;
+    use super::{match_pitches, Chord};
     use anyhow::Result;
-    use std::collections::BTreeSet;
+    use std::collections::{BTreeSet, HashMap};
 
     #[test]
     fn test_chord_modify() -> Result<()> {
@@ -213,6 +534,85 @@ mod tests {
         assert_eq!(chord.semitones, BTreeSet::from_iter(vec![0, 3, 7]));
         chord.modify(&Modifier::Major(7))?;
         assert_eq!(chord.semitones, BTreeSet::from_iter(vec![0, 4, 7, 11]));
+        chord.modify(&Modifier::Dominant(7))?;
+        assert_eq!(chord.semitones, BTreeSet::from_iter(vec![0, 4, 7, 10]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dominant() -> Result<()> {
+        let ast = RechordImporter::default().import("C7 Cmaj7 CM7 C9 C6 C5 C11 C13\n")?;
+        let semitones = ast
+            .into_iter()
+            .map(|node| match node {
+                Node::Chord(chord) => Ok(chord.to_chord()?.semitones),
+                _ => unreachable!(),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            semitones,
+            vec![
+                BTreeSet::from_iter(vec![0, 4, 7, 10]),
+                BTreeSet::from_iter(vec![0, 4, 7, 11]),
+                BTreeSet::from_iter(vec![0, 4, 7, 11]),
+                BTreeSet::from_iter(vec![0, 4, 7, 10, 14]),
+                BTreeSet::from_iter(vec![0, 4, 7, 9]),
+                BTreeSet::from_iter(vec![0, 7]),
+                BTreeSet::from_iter(vec![0, 4, 7, 10, 14, 17]),
+                BTreeSet::from_iter(vec![0, 4, 7, 10, 14, 21]),
+            ]
+        );
+        // a power chord keeps its name
+        assert_eq!(first_chord("C5")?.to_string(), "C5");
+        let ast = RechordImporter::default().import("C5 G5\n")?;
+        MidiExporter::default().export(&mut vec![], ast)?;
+        for chord in ["C3\n", "C1\n"] {
+            assert!(
+                RechordImporter::default().import(chord).is_err(),
+                "{}",
+                chord
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_minor_sixth() -> Result<()> {
+        let node = first_chord("Cm6")?;
+        assert_eq!(node.semitones()?, BTreeSet::from_iter(vec![0, 3, 7, 9]));
+        assert_eq!(
+            node.to_chord()?.semitones,
+            BTreeSet::from_iter(vec![0, 3, 7, 9])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rootless() -> Result<()> {
+        let code = "C7omit1 Cmaj7omit1\n";
+        let ast = RechordImporter::default().import(code)?;
+        let chords = ast
+            .into_iter()
+            .map(|node| match node {
+                Node::Chord(chord) => chord.to_chord(),
+                _ => unreachable!(),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            chords
+                .iter()
+                .map(|c| c.semitones.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                BTreeSet::from_iter(vec![4, 7, 10]),
+                BTreeSet::from_iter(vec![4, 7, 11]),
+            ]
+        );
+        for chord in &chords {
+            match_pitches(60, chord)?;
+        }
+        let ast = RechordImporter::default().import(code)?;
+        MidiExporter::default().export(&mut vec![], ast)?;
         Ok(())
     }
 
@@ -226,4 +626,285 @@ mod tests {
         assert_eq!(chord.semitones, BTreeSet::from_iter(vec![0, 4, 8]));
         Ok(())
     }
+
+    #[test]
+    fn test_modifier_order() -> Result<()> {
+        for (code, expected) in [
+            ("Caug7(b9)", vec![0, 4, 8, 10, 13]),
+            ("CM7aug", vec![0, 4, 8, 11]),
+            ("Cm7b5(11)", vec![0, 3, 6, 10, 17]),
+            ("C7sus4(9)", vec![0, 5, 7, 10, 14]),
+        ] {
+            let node = first_chord(code)?;
+            assert_eq!(
+                node.semitones()?,
+                BTreeSet::from_iter(expected.clone()),
+                "{}",
+                code
+            );
+            assert_eq!(node.to_chord()?.semitones, BTreeSet::from_iter(expected));
+        }
+        // structural qualities come first whatever the set order
+        let node = ChordNode {
+            modifiers: BTreeSet::from_iter(vec![
+                Modifier::Major(5),
+                Modifier::Sus4,
+                Modifier::Dominant(7),
+            ]),
+            ..ChordNode::absolute(Pitch::C)
+        };
+        let ordered = node.ordered_modifiers();
+        assert_eq!(ordered.last(), Some(&&Modifier::Sus4));
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_accidental() -> Result<()> {
+        for (modifier, expected) in [
+            (
+                Modifier::Add(Degree(9, Accidental::Flat)),
+                vec![0, 4, 7, 13],
+            ),
+            (
+                Modifier::Add(Degree(11, Accidental::Sharp)),
+                vec![0, 4, 7, 18],
+            ),
+            (
+                Modifier::Tension(Degree(9, Accidental::Flat)),
+                vec![0, 4, 7, 13],
+            ),
+        ] {
+            let mut chord = Chord::new(4, 0, Key::Absolute(Pitch::C));
+            chord.modify(&Modifier::Major(5))?;
+            chord.modify(&modifier)?;
+            assert_eq!(chord.semitones, BTreeSet::from_iter(expected));
+        }
+        let mut chord = Chord::new(4, 0, Key::Absolute(Pitch::C));
+        chord.modify(&Modifier::Major(5))?;
+        chord.modify(&Modifier::Flat5th)?;
+        chord.modify(&Modifier::Omit(Degree(5, Accidental::Flat)))?;
+        assert_eq!(chord.semitones, BTreeSet::from_iter(vec![0, 4]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_root_note_number() -> Result<()> {
+        let mut chord = Chord::new(5, 0, Key::Absolute(Pitch::C));
+        chord.modify(&Modifier::Major(5))?;
+        assert_eq!(chord.root_note_number()?, 72);
+        chord.inversion = 2;
+        assert_eq!(chord.root_note_number()?, 72);
+        assert_eq!(
+            Chord::new(4, 0, Key::Absolute(Pitch::A)).root_note_number()?,
+            69
+        );
+        assert!(Chord::new(5, 0, Key::Relative(0))
+            .root_note_number()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_substitutions() {
+        let chord = |pitch: Pitch, semitones: &[u8]| {
+            let mut chord = Chord::new(4, 0, Key::Absolute(pitch));
+            chord.semitones = BTreeSet::from_iter(semitones.iter().copied());
+            chord
+        };
+        let contains = |subs: &[Chord], expected: &Chord| {
+            subs.iter()
+                .any(|c| c.key == expected.key && c.semitones == expected.semitones)
+        };
+
+        let subs = chord(Pitch::G, &[0, 4, 7, 10]).substitutions();
+        assert!(contains(&subs, &chord(Pitch::Cs, &[0, 4, 7, 10])));
+        assert!(contains(&subs, &chord(Pitch::Gs, &[0, 3, 6, 9])));
+
+        let subs = chord(Pitch::C, &[0, 4, 7]).substitutions();
+        assert!(contains(&subs, &chord(Pitch::A, &[0, 3, 7])));
+
+        let subs = chord(Pitch::A, &[0, 3, 7]).substitutions();
+        assert!(contains(&subs, &chord(Pitch::C, &[0, 4, 7])));
+    }
+
+    #[test]
+    fn test_display() -> Result<()> {
+        let mut chord = Chord::new(4, 0, Key::Absolute(Pitch::C));
+        chord.modify(&Modifier::Major(5))?;
+        chord.modify(&Modifier::Minor(7))?;
+        assert_eq!(chord.to_string(), "Cm7");
+
+        let mut chord = Chord::new(4, 0, Key::Absolute(Pitch::A));
+        chord.modify(&Modifier::Major(5))?;
+        chord.modify(&Modifier::Minor(5))?;
+        chord.on = Some(Key::Absolute(Pitch::G));
+        assert_eq!(chord.to_string(), "Am/G");
+
+        let mut chord = Chord::new(4, 0, Key::Relative(7));
+        chord.modify(&Modifier::Major(5))?;
+        chord.modify(&Modifier::Dominant(7))?;
+        assert_eq!(chord.to_string(), "V7");
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash() -> Result<()> {
+        let ast = RechordImporter::default().import("C G | Am F | C G | C\n")?;
+        let mut counts = HashMap::new();
+        for node in ast {
+            if let Node::Chord(chord) = node {
+                *counts.entry(chord.to_chord()?).or_insert(0) += 1;
+            }
+        }
+        let c = ChordNode {
+            modifiers: BTreeSet::from_iter(vec![Modifier::Major(5)]),
+            ..ChordNode::absolute(Pitch::C)
+        };
+        assert_eq!(counts.len(), 4);
+        assert_eq!(counts[&c.to_chord()?], 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_diatonic_to() -> Result<()> {
+        let mut d = Chord::new(4, 0, Key::Absolute(Pitch::D));
+        d.modify(&Modifier::Major(5))?;
+        assert!(!d.is_diatonic_to(Pitch::C, Scale::Major));
+        assert!(d.is_diatonic_to(Pitch::D, Scale::Major));
+        d.modify(&Modifier::Minor(5))?;
+        assert!(d.is_diatonic_to(Pitch::C, Scale::Major));
+
+        let mut ii = Chord::new(4, 0, Key::Relative(2));
+        ii.modify(&Modifier::Major(5))?;
+        ii.modify(&Modifier::Minor(5))?;
+        assert!(ii.is_diatonic_to(Pitch::F, Scale::Major));
+        Ok(())
+    }
+
+    #[test]
+    fn test_quantize_to_nearest_quality() -> Result<()> {
+        let semitones = |notes: &[u8]| -> Result<Vec<u8>> {
+            let chord = Chord::quantize_to_nearest_quality(notes)?;
+            Ok(chord.semitones.into_iter().collect())
+        };
+        assert_eq!(semitones(&[60, 64, 67, 71])?, vec![0, 4, 7, 11]);
+        assert_eq!(semitones(&[60, 64, 67, 70])?, vec![0, 4, 7, 10]);
+        // doubled tones collapse
+        assert_eq!(semitones(&[48, 60, 64, 67, 70, 76])?, vec![0, 4, 7, 10]);
+
+        let chord = Chord::quantize_to_nearest_quality(&[64, 67, 72])?;
+        assert_eq!(chord.key, Key::Absolute(Pitch::C));
+        assert_eq!(chord.on, Some(Key::Absolute(Pitch::E)));
+        assert_eq!(chord.root_note_number()?, 72);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pitch_classes() -> Result<()> {
+        let mut chord = Chord::new(4, 0, Key::Absolute(Pitch::C));
+        chord.semitones = BTreeSet::from_iter(vec![0, 4, 7, 10]);
+        assert_eq!(
+            chord.pitch_classes()?,
+            BTreeSet::from_iter(vec![Pitch::C, Pitch::E, Pitch::G, Pitch::As])
+        );
+        assert!(Chord::new(4, 0, Key::Relative(0)).pitch_classes().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_common_tones() -> Result<()> {
+        let chord = |s| -> Result<Chord> { ChordNode::try_from(s)?.to_chord() };
+        assert_eq!(chord("C")?.common_tones(&chord("Am")?), vec![0, 4]);
+        assert_eq!(chord("G7")?.common_tones(&chord("Bdim")?), vec![2, 5, 11]);
+        assert!(chord("C")?.common_tones(&chord("F#")?).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_roman_quality_suffix() -> Result<()> {
+        for (code, suffix) in [
+            ("C", ""),
+            ("Cm", "m"),
+            ("Cdim", "°"),
+            ("Caug", "+"),
+            ("C7", "7"),
+            ("CM7", "maj7"),
+            ("Cm7", "m7"),
+            ("Cm7b5", "ø7"),
+            ("Cdim7", "°7"),
+        ] {
+            let chord = ChordNode::try_from(code)?.to_chord()?;
+            assert_eq!(chord.roman_quality_suffix(), suffix, "{}", code);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_info() -> Result<()> {
+        let info = ChordNode::try_from("Cm7")?.to_chord()?.info()?;
+        assert_eq!(info.notes, vec!["C", "D#", "G", "A#"]);
+        assert_eq!(info.pitch_classes, vec![0, 3, 7, 10]);
+        assert_eq!(info.intervals, vec![0, 3, 7, 10]);
+        assert_eq!(info.scale.as_deref(), Some("Dorian"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_drop() -> Result<()> {
+        let exporter = MidiExporter::default();
+        let mut chord = Chord::new(4, 0, Key::Absolute(Pitch::C));
+        chord.semitones = BTreeSet::from_iter(vec![0, 4, 7, 11]);
+        let notes = |chord: &Chord| -> Result<Vec<u8>> {
+            Ok(exporter
+                .note_numbers(chord)?
+                .iter()
+                .map(|n| n.get())
+                .collect())
+        };
+        assert_eq!(notes(&chord)?, vec![60, 64, 67, 71]);
+        assert_eq!(notes(&chord.drop2())?, vec![55, 60, 64, 71]);
+        assert_eq!(notes(&chord.drop3())?, vec![52, 60, 67, 71]);
+        // the inversion is voiced before dropping
+        chord.inversion = 1;
+        assert_eq!(notes(&chord.drop2())?, vec![59, 64, 67, 72]);
+        let mut triad = Chord::new(4, 0, Key::Absolute(Pitch::C));
+        triad.semitones = BTreeSet::from_iter(vec![0, 4, 7]);
+        assert_eq!(triad.drop2(), triad);
+        Ok(())
+    }
+
+    #[test]
+    fn test_inversions() -> Result<()> {
+        let exporter = MidiExporter::default();
+        let mut chord = Chord::new(4, 0, Key::Absolute(Pitch::C));
+        chord.semitones = BTreeSet::from_iter(vec![0, 4, 7]);
+        let voicings = chord
+            .inversions()
+            .iter()
+            .map(|c| Ok(exporter.note_numbers(c)?.iter().map(|n| n.get()).collect()))
+            .collect::<Result<Vec<Vec<_>>>>()?;
+        assert_eq!(
+            voicings,
+            vec![vec![60, 64, 67], vec![64, 67, 72], vec![67, 72, 76]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sus() -> Result<()> {
+        // sevenths and extensions keep the suspension, only an explicit add brings the third back
+        for (code, expected) in [
+            ("Csus2", vec![0, 2, 7]),
+            ("Csus4", vec![0, 5, 7]),
+            ("C7sus4", vec![0, 5, 7, 10]),
+            ("C9sus4", vec![0, 5, 7, 10, 14]),
+            ("CM7sus2", vec![0, 2, 7, 11]),
+            ("Csus4add7", vec![0, 5, 7, 11]),
+            ("Csus4add3", vec![0, 4, 5, 7]),
+        ] {
+            let node = first_chord(&format!("{}\n", code))?;
+            assert_eq!(node.semitones()?, BTreeSet::from_iter(expected), "{}", code);
+        }
+        Ok(())
+    }
 }
@@ -1,7 +1,7 @@
 use super::{pitch::Pitch, scale::Degree};
 use std::fmt::Display;
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum Key {
     Absolute(Pitch),
     // semitones
@@ -33,4 +33,14 @@ impl Key {
             }
         }
     }
+
+    /// returns the key raised by `semitones`
+    pub fn transpose(&self, semitones: u8) -> Key {
+        match self {
+            Key::Absolute(pitch) => {
+                Key::Absolute(Pitch::try_from((*pitch as u8 + semitones) % 12).unwrap())
+            }
+            Key::Relative(degree) => Key::Relative((degree + semitones) % 12),
+        }
+    }
 }
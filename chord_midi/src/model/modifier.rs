@@ -5,36 +5,141 @@ use std::fmt::Display;
 pub enum Modifier {
     Major(u8),
     Minor(u8),
+    /// major triad with a minor seventh (`7`, `9`)
+    Dominant(u8),
     MinorMajaor7,
     Sus2,
     Sus4,
+    /// root and fifth without the third (`5`)
+    Power,
     Flat5th,
     Aug,
     Aug7,
     Dim,
     Dim7,
-    Omit(u8),
-    Add(u8),
+    Omit(Degree),
+    Add(Degree),
     Tension(Degree),
 }
 
+impl Modifier {
+    /// returns when the modifier is applied: the quality builds the chord, then the third and fifth
+    /// are changed, then altered, then tensions are stacked and last degrees are left out
+    pub fn phase(&self) -> u8 {
+        match self {
+            Modifier::Major(_)
+            | Modifier::Minor(_)
+            | Modifier::Dominant(_)
+            | Modifier::MinorMajaor7 => 0,
+            Modifier::Sus2
+            | Modifier::Sus4
+            | Modifier::Power
+            | Modifier::Aug
+            | Modifier::Aug7
+            | Modifier::Dim
+            | Modifier::Dim7 => 1,
+            Modifier::Flat5th => 2,
+            Modifier::Add(_) | Modifier::Tension(_) => 3,
+            Modifier::Omit(_) => 4,
+        }
+    }
+
+    /// returns each token the rechord parser accepts after a root, with what it means
+    pub fn catalog() -> Vec<(&'static str, &'static str)> {
+        vec![
+            ("maj", "major triad"),
+            ("M", "major triad"),
+            ("m", "minor triad"),
+            ("min", "minor triad"),
+            ("5", "power chord"),
+            ("6", "major sixth"),
+            ("m6", "minor sixth"),
+            ("7", "dominant seventh"),
+            ("9", "dominant ninth"),
+            ("11", "dominant eleventh"),
+            ("13", "dominant thirteenth"),
+            ("maj7", "major seventh"),
+            ("M7", "major seventh"),
+            ("maj9", "major ninth"),
+            ("maj11", "major eleventh"),
+            ("maj13", "major thirteenth"),
+            ("m7", "minor seventh"),
+            ("m9", "minor ninth"),
+            ("mM7", "minor major seventh"),
+            ("sus2", "second instead of the third"),
+            ("sus4", "fourth instead of the third"),
+            ("dim", "diminished triad"),
+            ("o", "diminished triad"),
+            ("dim7", "diminished seventh"),
+            ("o7", "diminished seventh"),
+            ("aug", "augmented triad"),
+            ("+", "augmented triad"),
+            ("aug7", "augmented seventh"),
+            ("b5", "flatted fifth"),
+            ("-5", "flatted fifth"),
+            ("add9", "adds the ninth"),
+            ("add11", "adds the eleventh"),
+            ("add13", "adds the thirteenth"),
+            ("omit1", "leaves out the root"),
+            ("omit3", "leaves out the third"),
+            ("omit5", "leaves out the fifth"),
+            ("no3", "leaves out the third"),
+            ("no5", "leaves out the fifth"),
+            ("b9", "flat ninth tension"),
+            ("#9", "sharp ninth tension"),
+            ("#11", "sharp eleventh tension"),
+            ("b13", "flat thirteenth tension"),
+        ]
+    }
+}
+
 impl Display for Modifier {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Modifier::Major(5) => write!(f, ""),
-            Modifier::Major(d) => write!(f, "{}", d),
+            Modifier::Major(6) => write!(f, "6"),
+            Modifier::Major(d) => write!(f, "M{}", d),
+            Modifier::Minor(5) => write!(f, "m"),
             Modifier::Minor(d) => write!(f, "m{}", d),
+            Modifier::Dominant(d) => write!(f, "{}", d),
             Modifier::MinorMajaor7 => write!(f, "mM7"),
             Modifier::Sus2 => write!(f, "sus2"),
             Modifier::Sus4 => write!(f, "sus4"),
+            Modifier::Power => write!(f, "5"),
             Modifier::Flat5th => write!(f, "b5"),
             Modifier::Aug => write!(f, "aug"),
             Modifier::Aug7 => write!(f, "aug7"),
             Modifier::Dim => write!(f, "dim"),
             Modifier::Dim7 => write!(f, "dim7"),
-            Modifier::Omit(d) => write!(f, "omit{}", d),
-            Modifier::Add(d) => write!(f, "add{}", d),
-            Modifier::Tension(Degree(a, d)) => write!(f, "{}{}", a, d),
+            Modifier::Omit(Degree(d, a)) => write!(f, "omit{}{}", a, d),
+            Modifier::Add(Degree(d, a)) => write!(f, "add{}{}", a, d),
+            Modifier::Tension(Degree(d, a)) => write!(f, "{}{}", a, d),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Modifier;
+    use crate::import::{Importer, RechordImporter};
+    use crate::model::ast::Node;
+    use anyhow::Result;
+
+    #[test]
+    fn test_catalog() -> Result<()> {
+        let catalog = Modifier::catalog();
+        for token in ["maj7", "m7", "dim", "sus4", "add9", "7", "b9"] {
+            assert!(catalog.iter().any(|(t, _)| *t == token), "{}", token);
+        }
+        // every token parses and builds a chord, spelled as a degree since `C#11` reads as C#
+        for (token, _) in catalog {
+            let ast = RechordImporter::default().import(&format!("[C] I{}\n", token))?;
+            for node in ast {
+                if let Node::Chord(chord) = node {
+                    chord.to_chord()?;
+                }
+            }
         }
+        Ok(())
     }
 }
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use std::{fmt::Display, str::FromStr};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[repr(u8)]
 pub enum Pitch {
     C,
@@ -42,24 +42,25 @@ impl Display for Pitch {
 impl FromStr for Pitch {
     type Err = anyhow::Error;
 
+    /// accepts any letter with at most one accidental, so `Cb`, `Fb`, `E#` and `B#` wrap around
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        use Pitch::*;
-        match s {
-            "C" => Ok(C),
-            "C#" | "Db" => Ok(Cs),
-            "D" => Ok(D),
-            "D#" | "Eb" => Ok(Ds),
-            "E" | "Fb" => Ok(E),
-            "F" | "E#" => Ok(F),
-            "F#" | "Gb" => Ok(Fs),
-            "G" => Ok(G),
-            "G#" | "Ab" => Ok(Gs),
-            "A" => Ok(A),
-            "A#" | "Bb" => Ok(As),
-            "B" => Ok(B),
-            "B#" | "Cb" => Ok(Cs),
-            _ => Err(anyhow::anyhow!("invalid pitch: {}", s)),
-        }
+        let invalid = || anyhow::anyhow!("invalid pitch: {}", s);
+        let mut chars = s.chars();
+        let natural = match chars.next().ok_or_else(invalid)? {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => 5,
+            'G' => 7,
+            'A' => 9,
+            'B' => 11,
+            _ => return Err(invalid()),
+        };
+        let accidental = match chars.as_str() {
+            "" => Accidental::Natural,
+            a => Accidental::from_str(a).map_err(|_| invalid())?,
+        };
+        Pitch::try_from(((natural + i8::from(accidental) + 12) % 12) as u8)
     }
 }
 
@@ -85,7 +86,19 @@ impl TryFrom<u8> for Pitch {
     }
 }
 
+/// returns the note number of `pitch_class` semitones above C in `octave`.
+/// octaves follow scientific pitch notation, C4 is 60 and C-1 would be 0
+pub fn midi_note(octave: u8, pitch_class: u8) -> u8 {
+    12 * (octave + 1) + pitch_class
+}
+
 impl Pitch {
+    /// returns all pitches from C
+    pub fn all() -> [Pitch; 12] {
+        use Pitch::*;
+        [C, Cs, D, Ds, E, F, Fs, G, Gs, A, As, B]
+    }
+
     pub fn diff(&self, other: &Self) -> u8 {
         let a = *self as i8;
         let b = *other as i8;
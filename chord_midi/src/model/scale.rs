@@ -1,11 +1,19 @@
+use anyhow::Result;
 use std::fmt::Display;
 
+use super::ast::ChordNode;
+use super::modifier::Modifier;
 use super::pitch::{Accidental, Pitch};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Scale {
     Major,
     Minor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
 }
 
 impl Scale {
@@ -13,6 +21,24 @@ impl Scale {
         match self {
             Scale::Major => vec![2, 2, 1, 2, 2, 2, 1],
             Scale::Minor => vec![2, 1, 2, 2, 1, 2, 2],
+            Scale::Dorian => vec![2, 1, 2, 2, 2, 1, 2],
+            Scale::Phrygian => vec![1, 2, 2, 2, 1, 2, 2],
+            Scale::Lydian => vec![2, 2, 2, 1, 2, 2, 1],
+            Scale::Mixolydian => vec![2, 2, 1, 2, 2, 1, 2],
+            Scale::Locrian => vec![1, 2, 2, 1, 2, 2, 2],
+        }
+    }
+
+    /// returns the church mode name (`Ionian` for major, `Aeolian` for minor)
+    pub fn mode_name(&self) -> &'static str {
+        match self {
+            Scale::Major => "Ionian",
+            Scale::Minor => "Aeolian",
+            Scale::Dorian => "Dorian",
+            Scale::Phrygian => "Phrygian",
+            Scale::Lydian => "Lydian",
+            Scale::Mixolydian => "Mixolydian",
+            Scale::Locrian => "Locrian",
         }
     }
 
@@ -28,23 +54,63 @@ impl Scale {
     pub fn semitones(&self, degrees: &[u8]) -> Vec<u8> {
         degrees.iter().map(|d| self.semitone(*d)).collect()
     }
+
+    /// returns the triad stacked in thirds on `degree` (1-7) of the scale on `key`
+    pub fn chord_for_degree(&self, key: Pitch, degree: u8) -> Result<ChordNode> {
+        if !(1..=7).contains(&degree) {
+            return Err(anyhow::anyhow!("invalid degree: {}", degree));
+        }
+        let root = self.semitone(degree);
+        let third = self.semitone(degree + 2) - root;
+        let fifth = self.semitone(degree + 4) - root;
+        let mut chord = ChordNode::absolute(Pitch::all()[(key as u8 + root) as usize % 12]);
+        chord.modifiers.insert(Modifier::Major(5));
+        match (third, fifth) {
+            (3, 6) => chord.modifiers.insert(Modifier::Dim),
+            (3, _) => chord.modifiers.insert(Modifier::Minor(5)),
+            (4, 8) => chord.modifiers.insert(Modifier::Aug),
+            _ => false,
+        };
+        Ok(chord)
+    }
+
+    /// returns the seventh chord stacked in thirds on `degree` (1-7) of the scale on `key`,
+    /// `CM7 Dm7 Em7 FM7 G7 Am7 Bm7b5` in C major
+    pub fn seventh_chord_for_degree(&self, key: Pitch, degree: u8) -> Result<ChordNode> {
+        if !(1..=7).contains(&degree) {
+            return Err(anyhow::anyhow!("invalid degree: {}", degree));
+        }
+        let root = self.semitone(degree);
+        let third = self.semitone(degree + 2) - root;
+        let fifth = self.semitone(degree + 4) - root;
+        let seventh = self.semitone(degree + 6) - root;
+        let mut chord = ChordNode::absolute(Pitch::all()[(key as u8 + root) as usize % 12]);
+        chord.modifiers.insert(Modifier::Major(5));
+        let modifiers: &[Modifier] = match (third, fifth, seventh) {
+            (4, 7, 11) => &[Modifier::Major(7)],
+            (4, 7, 10) => &[Modifier::Dominant(7)],
+            (3, 7, 10) => &[Modifier::Minor(7)],
+            (3, 7, 11) => &[Modifier::MinorMajaor7],
+            (3, 6, 10) => &[Modifier::Minor(7), Modifier::Flat5th],
+            (3, 6, 9) => &[Modifier::Dim7],
+            (4, 8, 11) => &[Modifier::Aug, Modifier::Major(7)],
+            _ => &[],
+        };
+        chord.modifiers.extend(modifiers.iter().cloned());
+        Ok(chord)
+    }
 }
 
-fn to_roman_str(semitone: u8) -> &'static str {
-    match semitone {
-        0 => "I",
-        1 => "I#",
+fn to_roman_str(degree: u8) -> &'static str {
+    match degree {
+        1 => "I",
         2 => "II",
-        3 => "II#",
-        4 => "III",
-        5 => "IV",
-        6 => "IV#",
-        7 => "V",
-        8 => "V#",
-        9 => "VI",
-        10 => "VI#",
-        11 => "VII",
-        _ => panic!("invalid semitone: {}", semitone),
+        3 => "III",
+        4 => "IV",
+        5 => "V",
+        6 => "VI",
+        7 => "VII",
+        _ => panic!("invalid degree: {}", degree),
     }
 }
 
@@ -76,6 +142,12 @@ impl Degree {
         }
     }
 
+    /// returns semitone of the degree in `scale` shifted by its accidental
+    pub fn semitone(&self, scale: &Scale) -> u8 {
+        let a: i8 = self.1.clone().into();
+        (scale.semitone(self.0) as i8 + a) as u8
+    }
+
     pub fn with_pitch(&self, pitch: Pitch) -> Pitch {
         let i: i8 = self.1.clone().into();
         Pitch::try_from(((pitch as u8 as i8 + self.0 as i8 + i) % 12) as u8).unwrap()
@@ -84,7 +156,10 @@ impl Degree {
 
 #[cfg(test)]
 mod tests {
-    use super::Scale;
+    use super::{Degree, Scale};
+    use crate::import::{Importer, RechordImporter};
+    use crate::model::{ast::Node, pitch::Pitch};
+    use anyhow::Result;
 
     #[test]
     fn test_semitone() {
@@ -93,4 +168,63 @@ mod tests {
         assert_eq!(Scale::Major.semitone(3), 4);
         assert_eq!(Scale::Major.semitone(4), 5);
     }
+
+    #[test]
+    fn test_degree_display() {
+        assert_eq!(Degree::from_semitone(0).to_string(), "I");
+        assert_eq!(Degree::from_semitone(5).to_string(), "IV");
+        assert_eq!(Degree::from_semitone(6).to_string(), "IV#");
+        assert_eq!(Degree::from_semitone(11).to_string(), "VII");
+    }
+
+    #[test]
+    fn test_chord_for_degree() -> Result<()> {
+        let chords = (1..=7)
+            .map(|d| Ok(Node::Chord(Scale::Major.chord_for_degree(Pitch::C, d)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let expected = RechordImporter::default().import("C Dm Em F G Am Bdim\n")?;
+        assert_eq!(chords, expected.into_iter().collect::<Vec<_>>());
+
+        let chords = (1..=7)
+            .map(|d| Ok(Scale::Minor.chord_for_degree(Pitch::A, d)?.to_string()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(chords, vec!["Am", "Bdim", "C", "Dm", "Em", "F", "G"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_chord_for_degree_out_of_range() {
+        for degree in [0, 8] {
+            let message = format!("invalid degree: {}", degree);
+            let err = Scale::Major.chord_for_degree(Pitch::C, degree).unwrap_err();
+            assert_eq!(err.to_string(), message);
+            let err = Scale::Major
+                .seventh_chord_for_degree(Pitch::C, degree)
+                .unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_seventh_chord_for_degree() -> Result<()> {
+        let chords = (1..=7)
+            .map(|d| {
+                Ok(Node::Chord(
+                    Scale::Major.seventh_chord_for_degree(Pitch::C, d)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let expected = RechordImporter::default().import("CM7 Dm7 Em7 FM7 G7 Am7 Bm7b5\n")?;
+        assert_eq!(chords, expected.into_iter().collect::<Vec<_>>());
+
+        let quality = |d| -> Result<&str> {
+            let chord = Scale::Major
+                .seventh_chord_for_degree(Pitch::C, d)?
+                .to_chord()?;
+            Ok(chord.roman_quality_suffix())
+        };
+        assert_eq!(quality(5)?, "7");
+        assert_eq!(quality(7)?, "ø7");
+        Ok(())
+    }
 }
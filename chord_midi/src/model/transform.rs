@@ -1,8 +1,52 @@
 use crate::{
-    model::ast::{Ast, ChordNode, Node},
+    model::ast::{voices, Ast, ChordNode, Node},
+    model::key::Key,
     model::pitch::Pitch,
 };
 
+/// a node-level change in the measure at the given index (0-origin)
+#[derive(Debug, PartialEq)]
+pub enum MeasureDiff {
+    Added(usize, Node),
+    Removed(usize, Node),
+    Changed(usize, Node, Node),
+}
+
+impl ChordNode {
+    pub fn into_degree(self, key: Pitch) -> ChordNode {
+        ChordNode {
+            key: self.key.into_degree(key),
+            on: self.on.map(|on| on.into_degree(key)),
+            grace: self.grace.map(|g| Box::new(g.into_degree(key))),
+            alternate: self.alternate.map(|a| Box::new(a.into_degree(key))),
+            ..self
+        }
+    }
+
+    pub fn into_pitch(self, pitch: Pitch) -> ChordNode {
+        ChordNode {
+            key: self.key.into_pitch(pitch),
+            on: self.on.map(|on| on.into_pitch(pitch)),
+            grace: self.grace.map(|g| Box::new(g.into_pitch(pitch))),
+            alternate: self.alternate.map(|a| Box::new(a.into_pitch(pitch))),
+            ..self
+        }
+    }
+}
+
+impl Node {
+    /// applies `f` to the chord or the chords of a tuplet
+    pub fn map_chords(self, f: &impl Fn(ChordNode) -> ChordNode) -> Node {
+        match self {
+            Node::Chord(chord) => Node::Chord(f(chord)),
+            Node::Tuplet(nodes) => {
+                Node::Tuplet(nodes.into_iter().map(|n| n.map_chords(f)).collect())
+            }
+            node => node,
+        }
+    }
+}
+
 impl Ast {
     pub fn into_degree(self, key: Pitch) -> Ast {
         match self {
@@ -15,14 +59,7 @@ impl Ast {
             Ast::Measure(nodes, br) => Ast::Measure(
                 nodes
                     .into_iter()
-                    .map(|node| match node {
-                        Node::Chord(chord) => Node::Chord(ChordNode {
-                            key: chord.key.into_degree(key),
-                            modifiers: chord.modifiers,
-                            on: chord.on.map(|on| on.into_degree(key)),
-                        }),
-                        _ => node,
-                    })
+                    .map(|node| node.map_chords(&|chord| chord.into_degree(key)))
                     .collect::<Vec<_>>(),
                 br,
             ),
@@ -41,43 +78,533 @@ impl Ast {
             Ast::Measure(nodes, br) => Ast::Measure(
                 nodes
                     .into_iter()
-                    .map(|node| match node {
-                        Node::Chord(chord) => Node::Chord(ChordNode {
-                            key: chord.key.into_pitch(pitch),
-                            modifiers: chord.modifiers,
-                            on: chord.on.map(|on| on.into_pitch(pitch)),
-                        }),
-                        _ => node,
-                    })
+                    .map(|node| node.map_chords(&|chord| chord.into_pitch(pitch)))
                     .collect::<Vec<_>>(),
                 br,
             ),
             other => other,
         }
     }
+
+    /// reverses the order of measures and nodes keeping each chord's duration.
+    /// `=` stays behind the chord it sustains, `%` is replaced by the chord it repeats,
+    /// hairpins are swapped, and comments/markers stay before the measure they precede.
+    /// each `&` voice is reversed on its own
+    pub fn reverse(self) -> Ast {
+        match self {
+            Ast::Score(score) => {
+                let mut groups = vec![];
+                let mut pending = vec![];
+                // each measure's `&` voices, split from their leading `{n}`
+                let mut measures = vec![];
+                let mut brs = vec![];
+                for ast in score {
+                    match *ast {
+                        Ast::Measure(nodes, br) => {
+                            groups.push(std::mem::take(&mut pending));
+                            let voices = voices(&nodes)
+                                .into_iter()
+                                .map(|voice| split_subdivision(voice.to_vec()))
+                                .collect::<Vec<_>>();
+                            measures.push(voices);
+                            brs.push(br);
+                        }
+                        other => pending.push(other),
+                    }
+                }
+
+                // each voice runs backwards through the measures it appears in
+                let count = measures
+                    .iter()
+                    .map(|voices| voices.len())
+                    .max()
+                    .unwrap_or(1);
+                let mut reversed_measures = vec![vec![]; measures.len()];
+                for i in 0..count {
+                    let nodes = measures
+                        .iter()
+                        .filter_map(|voices| voices.get(i))
+                        .flat_map(|(_, nodes)| nodes.clone());
+                    let mut nodes = reverse_nodes(nodes).into_iter().peekable();
+                    for (measure, voices) in reversed_measures.iter_mut().zip(measures.iter().rev())
+                    {
+                        let Some((subdivision, voice)) = voices.get(i) else {
+                            continue;
+                        };
+                        let len = voice.iter().filter(|n| n.is_timed()).count();
+                        let mut reversed = subdivision.iter().cloned().collect::<Vec<_>>();
+                        let mut timed = 0;
+                        while let Some(node) = nodes.next_if(|n| timed < len || !n.is_timed()) {
+                            if node.is_timed() {
+                                timed += 1;
+                            }
+                            reversed.push(node);
+                        }
+                        measure.push(reversed);
+                    }
+                }
+
+                let mut reversed = vec![];
+                let measures = reversed_measures.into_iter().zip(groups.into_iter().rev());
+                for (i, (voices, group)) in measures.enumerate() {
+                    reversed.extend(group.into_iter().map(Box::new));
+                    reversed.push(Box::new(Ast::Measure(voices.join(&Node::Voice), brs[i])));
+                }
+                reversed.extend(pending.into_iter().map(Box::new));
+                Ast::Score(reversed)
+            }
+            Ast::Measure(nodes, br) => {
+                let voices = voices(&nodes)
+                    .into_iter()
+                    .map(|voice| {
+                        let (subdivision, nodes) = split_subdivision(voice.to_vec());
+                        subdivision
+                            .into_iter()
+                            .chain(reverse_nodes(nodes))
+                            .collect()
+                    })
+                    .collect::<Vec<Vec<_>>>();
+                Ast::Measure(voices.join(&Node::Voice), br)
+            }
+            other => other,
+        }
+    }
+
+    /// returns the chart written out for tools that don't read repeats: nested scores are
+    /// opened and `%` becomes the chord it repeats in its `&` voice, without the grace chord
+    /// or push of that chord's onset. `%%` is already written out on import
+    pub fn flatten(&self) -> Ast {
+        let mut pre: Vec<Option<ChordNode>> = vec![];
+        let items = items(self.clone()).into_iter().map(|ast| match ast {
+            Ast::Measure(nodes, br) => {
+                let mut voice = 0;
+                let nodes = nodes
+                    .into_iter()
+                    .map(|node| {
+                        if node == Node::Voice {
+                            voice += 1;
+                        }
+                        if pre.len() <= voice {
+                            pre.resize(voice + 1, None);
+                        }
+                        flatten_node(node, &mut pre[voice])
+                    })
+                    .collect();
+                Ast::Measure(nodes, br)
+            }
+            other => other,
+        });
+        Ast::Score(items.map(Box::new).collect())
+    }
+
+    /// splits measures at `&` into one chart per voice. a measure without the voice rests,
+    /// and comments and markers stay in the first voice
+    pub fn into_voices(self) -> Vec<Ast> {
+        let count = measures(&self)
+            .iter()
+            .map(|nodes| voices(nodes).len())
+            .max()
+            .unwrap_or(1);
+        (0..count).map(|i| self.clone().into_voice(i)).collect()
+    }
+
+    fn into_voice(self, i: usize) -> Ast {
+        match self {
+            Ast::Score(score) => Ast::Score(
+                score
+                    .into_iter()
+                    .filter(|ast| i == 0 || !matches!(**ast, Ast::Comment(_) | Ast::Marker(_)))
+                    .map(|ast| Box::new(ast.into_voice(i)))
+                    .collect(),
+            ),
+            Ast::Measure(nodes, br) => {
+                let voice = voices(&nodes).get(i).map(|v| v.to_vec());
+                Ast::Measure(voice.unwrap_or(vec![Node::Rest]), br)
+            }
+            other => other,
+        }
+    }
+
+    /// plays `other` after the chart
+    pub fn concat(self, other: Ast) -> Ast {
+        let items = items(self).into_iter().chain(items(other));
+        Ast::Score(items.map(Box::new).collect())
+    }
+
+    /// plays `other` along with the chart as further `&` voices, the shorter one is padded with rests
+    pub fn overlay(self, other: Ast) -> Ast {
+        let width = measures(&self)
+            .iter()
+            .map(|nodes| voices(nodes).len())
+            .max()
+            .unwrap_or(1);
+        let pad = |mut nodes: Vec<Node>| {
+            for _ in voices(&nodes).len()..width {
+                nodes.extend([Node::Voice, Node::Rest]);
+            }
+            nodes
+        };
+        let mut others = items(other).into_iter().filter_map(|ast| match ast {
+            Ast::Measure(nodes, br) => Some((nodes, br)),
+            _ => None,
+        });
+        let mut score = vec![];
+        for ast in items(self) {
+            match ast {
+                Ast::Measure(nodes, br) => {
+                    let mut nodes = pad(nodes);
+                    nodes.push(Node::Voice);
+                    match others.next() {
+                        Some((other, _)) => nodes.extend(other),
+                        None => nodes.push(Node::Rest),
+                    }
+                    score.push(Box::new(Ast::Measure(nodes, br)));
+                }
+                other => score.push(Box::new(other)),
+            }
+        }
+        for (other, br) in others {
+            let mut nodes = pad(vec![Node::Rest]);
+            nodes.push(Node::Voice);
+            nodes.extend(other);
+            score.push(Box::new(Ast::Measure(nodes, br)));
+        }
+        Ast::Score(score)
+    }
+
+    /// compares measures position by position, ignoring comments and markers
+    pub fn diff(&self, other: &Ast) -> Vec<MeasureDiff> {
+        let (lhs, rhs) = (measures(self), measures(other));
+        let mut diffs = vec![];
+        for i in 0..lhs.len().max(rhs.len()) {
+            let a = lhs.get(i).copied().unwrap_or_default();
+            let b = rhs.get(i).copied().unwrap_or_default();
+            for j in 0..a.len().max(b.len()) {
+                match (a.get(j), b.get(j)) {
+                    (Some(x), Some(y)) if x != y => {
+                        diffs.push(MeasureDiff::Changed(i, x.clone(), y.clone()))
+                    }
+                    (Some(x), None) => diffs.push(MeasureDiff::Removed(i, x.clone())),
+                    (None, Some(y)) => diffs.push(MeasureDiff::Added(i, y.clone())),
+                    _ => {}
+                }
+            }
+        }
+        diffs
+    }
+
+    /// returns semitones from each chord's root to the next one's, the shorter way round (-5..=6)
+    pub fn root_motions(&self) -> Vec<i8> {
+        let roots = progression(self)
+            .into_iter()
+            .map(|chord| match chord.key {
+                Key::Relative(s) => s as i8,
+                Key::Absolute(p) => p as i8,
+            })
+            .collect::<Vec<_>>();
+        roots
+            .windows(2)
+            .map(|w| match (w[1] - w[0]).rem_euclid(12) {
+                d if d > 6 => d - 12,
+                d => d,
+            })
+            .collect()
+    }
+
+    /// returns whether both charts move between the same chords, ignoring the key and rhythm
+    pub fn same_progression(&self, other: &Ast) -> bool {
+        progression(self) == progression(other)
+    }
+}
+
+/// chords as degrees above the first chord's root, so transposed charts compare equal
+fn progression(ast: &Ast) -> Vec<ChordNode> {
+    let chords = ast
+        .clone()
+        .into_iter()
+        .filter_map(|node| match node {
+            Node::Chord(chord) => Some(chord.into_degree(Pitch::C)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let Some(Key::Relative(first)) = chords.first().map(|chord| chord.key.clone()) else {
+        return vec![];
+    };
+    chords
+        .into_iter()
+        .map(|chord| ChordNode {
+            key: chord.key.transpose(12 - first),
+            on: chord.on.map(|on| on.transpose(12 - first)),
+            grace: None,
+            push: false,
+            ring: false,
+            staccato: false,
+            alternate: None,
+            ..chord
+        })
+        .collect()
+}
+
+/// writes out `%` as `pre`, the last chord of the voice
+fn flatten_node(node: Node, pre: &mut Option<ChordNode>) -> Node {
+    match node {
+        Node::Repeat => match pre {
+            Some(chord) => Node::Chord(ChordNode {
+                grace: None,
+                push: false,
+                ..chord.clone()
+            }),
+            None => Node::Repeat,
+        },
+        Node::Chord(chord) => {
+            *pre = Some(chord.clone());
+            Node::Chord(chord)
+        }
+        Node::Tuplet(nodes) => {
+            Node::Tuplet(nodes.into_iter().map(|n| flatten_node(n, pre)).collect())
+        }
+        node => node,
+    }
+}
+
+/// returns the items of a score, nested scores are opened
+fn items(ast: Ast) -> Vec<Ast> {
+    match ast {
+        Ast::Score(score) => score.into_iter().flat_map(|ast| items(*ast)).collect(),
+        other => vec![other],
+    }
+}
+
+fn measures(ast: &Ast) -> Vec<&[Node]> {
+    match ast {
+        Ast::Score(score) => score.iter().flat_map(|ast| measures(ast)).collect(),
+        Ast::Measure(nodes, _) => vec![nodes.as_slice()],
+        Ast::Comment(_) | Ast::Marker(_) | Ast::Directive(_) => vec![],
+    }
+}
+
+/// separates a leading `{n}`, which belongs to the measure rather than to a node
+fn split_subdivision(mut nodes: Vec<Node>) -> (Option<Node>, Vec<Node>) {
+    match nodes.first() {
+        Some(Node::Subdivision(_)) => (Some(nodes.remove(0)), nodes),
+        _ => (None, nodes),
+    }
+}
+
+fn reverse_nodes(nodes: impl IntoIterator<Item = Node>) -> Vec<Node> {
+    // each group is a node followed by the sustains extending it
+    let mut groups: Vec<Vec<Node>> = vec![];
+    let mut pre: Option<Node> = None;
+    for node in nodes {
+        let node = match node {
+            Node::Repeat => pre.clone().unwrap_or(Node::Repeat),
+            Node::Crescendo => Node::Decrescendo,
+            Node::Decrescendo => Node::Crescendo,
+            Node::Tuplet(nodes) => Node::Tuplet(reverse_nodes(nodes)),
+            node => node,
+        };
+        if let Node::Chord(_) = &node {
+            pre = Some(node.clone());
+        }
+        match groups.last_mut() {
+            Some(group) if node == Node::Sustain && group[0].is_timed() => group.push(node),
+            _ => groups.push(vec![node]),
+        }
+    }
+    groups.into_iter().rev().flatten().collect()
 }
 
 #[cfg(test)]
 mod tests {
+    use super::{measures, MeasureDiff};
+    use crate::import::{Importer, RechordImporter};
     use crate::model::{
         ast::{Ast, ChordNode, Node},
+        modifier::Modifier,
         pitch::Pitch,
     };
+    use anyhow::Result;
+
+    fn chord(pitch: Pitch) -> Node {
+        Node::Chord(ChordNode::absolute(pitch))
+    }
+
+    #[test]
+    fn test_flatten() -> Result<()> {
+        let importer = RechordImporter::default();
+        let ast = importer.import("[A]\n<D>C G | Am F | %%\n% (C %)2 & E | % & %\n")?;
+        let expected =
+            importer.import("[A]\n<D>C G | Am F | <D>C G | Am F\nF (C C)2 & E | C & E\n")?;
+        let ast = ast.flatten();
+        assert_eq!(ast, expected);
+        assert!(!ast.to_string().contains('%'));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reverse() {
+        let ast = Ast::Score(vec![
+            Box::new(Ast::Measure(vec![chord(Pitch::C), chord(Pitch::G)], false)),
+            Box::new(Ast::Measure(vec![chord(Pitch::A), chord(Pitch::F)], true)),
+        ]);
+        assert_eq!(
+            ast.reverse(),
+            Ast::Score(vec![
+                Box::new(Ast::Measure(vec![chord(Pitch::F), chord(Pitch::A)], false)),
+                Box::new(Ast::Measure(vec![chord(Pitch::G), chord(Pitch::C)], true)),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_reverse_sustain() {
+        let ast = Ast::Score(vec![
+            Box::new(Ast::Comment("intro".to_string())),
+            Box::new(Ast::Measure(vec![chord(Pitch::C), Node::Sustain], false)),
+            Box::new(Ast::Measure(vec![Node::Sustain, chord(Pitch::G)], false)),
+            Box::new(Ast::Measure(vec![Node::Repeat], false)),
+        ]);
+        assert_eq!(
+            ast.reverse(),
+            Ast::Score(vec![
+                Box::new(Ast::Measure(vec![chord(Pitch::G)], false)),
+                Box::new(Ast::Measure(vec![chord(Pitch::G), chord(Pitch::C)], false)),
+                Box::new(Ast::Comment("intro".to_string())),
+                Box::new(Ast::Measure(vec![Node::Sustain, Node::Sustain], false)),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_reverse_voices() -> Result<()> {
+        let ast = RechordImporter::default().import("C G & Am | F & Dm Em | E\n")?;
+        let expected = RechordImporter::default().import("E | F & Em Dm | G C & Am\n")?;
+        assert_eq!(ast.reverse(), expected);
+        let measure = Ast::Measure(
+            vec![
+                chord(Pitch::C),
+                chord(Pitch::G),
+                Node::Voice,
+                chord(Pitch::A),
+                chord(Pitch::F),
+            ],
+            false,
+        );
+        let reversed = Ast::Measure(
+            vec![
+                chord(Pitch::G),
+                chord(Pitch::C),
+                Node::Voice,
+                chord(Pitch::F),
+                chord(Pitch::A),
+            ],
+            false,
+        );
+        assert_eq!(measure.reverse(), reversed);
+        Ok(())
+    }
 
     #[test]
     fn test_transpose() {
-        let i = Node::Chord(ChordNode::relative(1));
+        let i = Node::Chord(ChordNode::relative(0));
         let c = Node::Chord(ChordNode::absolute(Pitch::C));
         assert_eq!(
             Ast::Measure(vec![i], false).into_pitch(Pitch::C),
             Ast::Measure(vec![c], false)
         );
 
-        let iv = Node::Chord(ChordNode::relative(6));
+        let iv = Node::Chord(ChordNode::relative(5));
         let f = Node::Chord(ChordNode::absolute(Pitch::F));
         assert_eq!(
             Ast::Measure(vec![iv], false).into_pitch(Pitch::C),
             Ast::Measure(vec![f], false)
         );
     }
+
+    #[test]
+    fn test_into_voices() {
+        let ast = Ast::Score(vec![
+            Box::new(Ast::Marker("A".to_string())),
+            Box::new(Ast::Measure(
+                vec![chord(Pitch::C), Node::Voice, chord(Pitch::E)],
+                false,
+            )),
+            Box::new(Ast::Measure(vec![chord(Pitch::G)], false)),
+        ]);
+        assert_eq!(
+            ast.into_voices(),
+            vec![
+                Ast::Score(vec![
+                    Box::new(Ast::Marker("A".to_string())),
+                    Box::new(Ast::Measure(vec![chord(Pitch::C)], false)),
+                    Box::new(Ast::Measure(vec![chord(Pitch::G)], false)),
+                ]),
+                Ast::Score(vec![
+                    Box::new(Ast::Measure(vec![chord(Pitch::E)], false)),
+                    Box::new(Ast::Measure(vec![Node::Rest], false)),
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_concat() -> Result<()> {
+        let verse = RechordImporter::default().import("C | G\n")?;
+        let chorus = RechordImporter::default().import("[Chorus]\nAm | F\n")?;
+        let song = verse.concat(chorus);
+        assert_eq!(measures(&song).len(), 4);
+        let expected = RechordImporter::default().import("C | G\n[Chorus]\nAm | F\n")?;
+        assert_eq!(song, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_overlay() -> Result<()> {
+        let chords = RechordImporter::default().import("C | G & B | F\n")?;
+        let bass = RechordImporter::default().import("C | G\n")?;
+        let expected = RechordImporter::default().import("C & _ & C | G & B & G | F & _ & _\n")?;
+        assert_eq!(chords.overlay(bass), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff() -> Result<()> {
+        let v1 = RechordImporter::default().import("C G | Am F\n")?;
+        let v2 = RechordImporter::default().import("C G | Am Dm\n")?;
+        let mut f = ChordNode::absolute(Pitch::F);
+        f.modifiers.insert(Modifier::Major(5));
+        let mut dm = ChordNode::absolute(Pitch::D);
+        dm.modifiers
+            .extend([Modifier::Major(5), Modifier::Minor(5)]);
+        assert_eq!(
+            v1.diff(&v2),
+            vec![MeasureDiff::Changed(1, Node::Chord(f), Node::Chord(dm))]
+        );
+        assert_eq!(v1.diff(&v1), vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_progression() -> Result<()> {
+        let importer = RechordImporter::default();
+        let ast = importer.import("C G | Am F\n")?;
+        assert!(ast.same_progression(&importer.import("D A | Bm G\n")?));
+        assert!(ast.same_progression(&importer.import("[C] I V VIm IV\n")?));
+        assert!(!ast.same_progression(&importer.import("D A | B G\n")?));
+        assert!(!ast.same_progression(&importer.import("D A | G Bm\n")?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_root_motions() -> Result<()> {
+        let importer = RechordImporter::default();
+        assert_eq!(importer.import("C F Bb\n")?.root_motions(), vec![5, 5]);
+        assert_eq!(importer.import("Dm7 G7 | C\n")?.root_motions(), vec![5, 5]);
+        assert_eq!(
+            importer.import("C Am F G\n")?.root_motions(),
+            vec![-3, -4, 2]
+        );
+        assert_eq!(importer.import("C F#\n")?.root_motions(), vec![6]);
+        Ok(())
+    }
 }
@@ -1,63 +1,527 @@
-use anyhow::Result;
-use chord_midi::export::{Exporter, RechordExporter};
+use anyhow::{anyhow, Result};
+use chord_midi::export::{
+    ChordTrackJsonExporter, Exporter, NoteValue, RechordExporter, SonicPiExporter,
+    TransposeAllExporter,
+};
 use chord_midi::import::{Importer, SexpImporter};
+use chord_midi::model::ast::Ast;
+use chord_midi::model::{chord::Chord, pitch::Pitch};
 use chord_midi::{export::MidiExporter, import::RechordImporter};
 use clap::Parser as _;
+use notify::{RecursiveMode, Watcher};
 use std::{
+    collections::HashMap,
     fs::{File, OpenOptions},
-    io::Read,
-    path::PathBuf,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
 };
 
+/// events arriving within this window are coalesced into one render
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
 #[derive(Debug, clap::Parser)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// a chart, or a directory of charts played one after another by filename
     #[arg(short, long)]
     input: PathBuf,
+    /// required unless a subcommand is given
     #[arg(short, long)]
-    output: PathBuf,
-    #[arg(long, default_value_t = 180)]
-    bpm: u8,
+    output: Option<PathBuf>,
+    #[arg(long, default_value_t = 180.0)]
+    bpm: f32,
+    /// octave whose C is note number 60
+    #[arg(long, default_value_t = 4)]
+    middle_c_octave: u8,
+    /// velocity of note-off events
+    #[arg(long, default_value_t = 72)]
+    release_velocity: u8,
+    /// key to render degree charts (`I IV V`) in
+    #[arg(long, default_value = "C")]
+    key: Pitch,
+    /// measures per line of text output, 0 keeps the input's line breaks
+    #[arg(long, default_value_t = 0)]
+    measures_per_line: usize,
+    /// align barlines of text output in columns
+    #[arg(long)]
+    align: bool,
+    /// accept lower-case pitches and case-insensitive chord qualities
+    #[arg(long)]
+    lenient: bool,
+    /// accept commas between chords in a measure
+    #[arg(long)]
+    commas: bool,
+    /// reject measures without exactly one chord, rest or `=` per beat
+    #[arg(long)]
+    chord_per_beat: bool,
+    /// also write the note names of each chord next to the MIDI file as `.txt`
+    #[arg(long)]
+    debug_notes: bool,
+    /// re-render whenever the input file changes
+    #[arg(long)]
+    watch: bool,
+    /// vary inversions and tensions of each chord, the same seed renders the same MIDI
+    #[arg(long)]
+    seed: Option<u64>,
+    /// sixteenths each chord lasts, ignoring how many share a measure
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    node_length: Option<u32>,
+    /// print the parsed chart before exporting it
+    #[arg(long)]
+    print_ast: bool,
+    /// render only the bass note of each chord
+    #[arg(long)]
+    bass_only: bool,
+    /// hold each chord with the sustain pedal
+    #[arg(long)]
+    pedal: bool,
+    /// write soprano, alto, tenor and bass of each chord to their own channels
+    #[arg(long)]
+    split_voices: bool,
+    /// play the alternate of `C(Am)` instead of the chord
+    #[arg(long)]
+    alternates: bool,
+    /// ticks between the notes of a strummed chord, a sixteenth is 256
+    #[arg(long, default_value_t = 0)]
+    strum: u32,
+    /// strum every other chord downward from its top note
+    #[arg(long)]
+    alternate_strum: bool,
+    /// where swung off-beats land within their pair, 0.5 is straight and 0.67 a triplet feel
+    #[arg(long, default_value_t = 0.5, value_parser = parse_swing)]
+    swing: f32,
+    /// swing sixteenths instead of eighths
+    #[arg(long)]
+    swing_sixteenths: bool,
 }
 
-fn extension(path: &PathBuf) -> String {
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    /// print each unique chord of the input with its count and total beats
+    Analyze,
+    /// write the chart, taken in `--key`, in all 12 keys to the output
+    TransposeAll,
+}
+
+fn parse_swing(s: &str) -> Result<f32> {
+    let swing = s.parse::<f32>()?;
+    if !(swing > 0.0 && swing < 1.0) {
+        return Err(anyhow!("swing must be between 0 and 1: {}", swing));
+    }
+    Ok(swing)
+}
+
+fn extension(path: &Path) -> String {
     path.extension()
         .unwrap_or_default()
         .to_string_lossy()
         .to_string()
 }
 
-fn main() -> Result<()> {
-    simplelog::SimpleLogger::init(log::LevelFilter::Debug, Default::default())?;
-    let args = Cli::try_parse()?;
+/// reads and imports the input, a directory becomes a medley of its charts
+fn import(args: &Cli) -> Result<Ast> {
+    if args.input.is_dir() {
+        return medley(args, &args.input);
+    }
+    import_file(args, &args.input)
+}
+
+/// concatenates the charts of `dir` sorted by filename, each starting with its name as a marker.
+/// hidden files are skipped
+fn medley(args: &Cli, dir: &Path) -> Result<Ast> {
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    paths.retain(|path| {
+        let hidden = path
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with('.'));
+        path.is_file() && !hidden
+    });
+    paths.sort();
+    let mut ast = Ast::Score(vec![]);
+    for path in paths {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let chart = Ast::Score(vec![Box::new(Ast::Marker(name.to_string()))]);
+        ast = ast.concat(chart.concat(import_file(args, &path)?));
+    }
+    Ok(ast)
+}
 
-    let mut f = File::open(&args.input)?;
+fn import_file(args: &Cli, path: &Path) -> Result<Ast> {
+    let mut f = File::open(path)?;
     let mut code = String::new();
     f.read_to_string(&mut code)?;
-    // CR+LF to LF
-    code = code.replace("\r\n", "\n");
+    // CR+LF and CR to LF
+    code = code.replace("\r\n", "\n").replace('\r', "\n");
 
-    let importer = match extension(&args.input).as_str() {
+    let importer = match extension(path).as_str() {
         "sexp" => Box::new(SexpImporter) as Box<dyn Importer>,
-        _ => Box::new(RechordImporter) as Box<dyn Importer>,
+        _ => Box::new(RechordImporter {
+            lenient: args.lenient,
+            commas: args.commas,
+            chord_per_beat: args.chord_per_beat,
+        }) as Box<dyn Importer>,
     };
-    let ast = importer.import(code.as_str())?;
+    importer
+        .import(code.as_str())
+        .map_err(|e| anyhow!("{}: {}", path.display(), e))
+}
+
+/// returns (chord, count, total beats) of each unique chord, most frequent first
+fn histogram(args: &Cli, ast: Ast) -> Result<Vec<(Chord, usize, f64)>> {
+    let exporter = MidiExporter::builder().key(args.key).build();
+    let mut histogram: Vec<(Chord, usize, f64)> = vec![];
+    let mut index = HashMap::new();
+    for (_, duration, chord) in exporter.chord_timeline(ast)? {
+        let i = *index.entry(chord.clone()).or_insert_with(|| {
+            histogram.push((chord, 0, 0.0));
+            histogram.len() - 1
+        });
+        histogram[i].1 += 1;
+        histogram[i].2 += duration;
+    }
+    // stable, so ties keep the order of first appearance
+    histogram.sort_by_key(|(_, count, _)| std::cmp::Reverse(*count));
+    Ok(histogram)
+}
+
+fn analyze(args: &Cli) -> Result<()> {
+    let ast = import(args)?;
+    for (chord, count, beats) in histogram(args, ast)? {
+        println!("{}\t{}\t{}", chord, count, beats);
+    }
+    Ok(())
+}
+
+/// exports the input in all twelve keys, reporting progress to `log`
+fn transpose_all(args: &Cli, log: &mut impl Write) -> Result<()> {
+    let ast = import(args)?;
+    let output = args
+        .output
+        .as_ref()
+        .ok_or_else(|| anyhow!("--output is required"))?;
+    let exporter = TransposeAllExporter {
+        key: args.key,
+        chart: RechordExporter {
+            measures_per_line: args.measures_per_line,
+            align: args.align,
+        },
+    };
+    exporter.export(&mut File::create(output)?, ast)?;
+    writeln!(log, "Exported to {}", output.display())?;
+    Ok(())
+}
+
+/// imports the input and exports it to the output once, reporting progress to `log`
+fn render(args: &Cli, log: &mut impl Write) -> Result<()> {
+    let ast = import(args)?;
+    if args.print_ast {
+        writeln!(log, "{:#?}", ast)?;
+    }
+    let output = args
+        .output
+        .as_ref()
+        .ok_or_else(|| anyhow!("--output is required"))?;
 
     let mut out = OpenOptions::new()
         .create(true)
         .write(true)
         .truncate(true)
-        .open(&args.output)
+        .open(output)
         .unwrap();
 
-    match extension(&args.output).as_str() {
+    match extension(output).as_str() {
         "midi" => {
-            MidiExporter { bpm: args.bpm }.export(&mut out, ast)?;
-            println!("Exported to {}", args.output.display());
+            let swing_subdivision = if args.swing_sixteenths {
+                NoteValue::Sixteenth
+            } else {
+                NoteValue::Eighth
+            };
+            let mut builder = MidiExporter::builder()
+                .bpm(args.bpm)
+                .middle_c_octave(args.middle_c_octave)
+                .release_velocity(args.release_velocity)
+                .key(args.key)
+                .bass_only(args.bass_only)
+                .pedal(args.pedal)
+                .split_voices(args.split_voices)
+                .alternates(args.alternates)
+                .strum_ticks(args.strum)
+                .alternate_strum(args.alternate_strum)
+                .swing(args.swing, swing_subdivision);
+            if let Some(seed) = args.seed {
+                builder = builder.voicing_seed(seed);
+            }
+            if let Some(sixteenths) = args.node_length {
+                builder = builder.node_length(sixteenths);
+            }
+            let exporter = builder.build();
+            if args.debug_notes {
+                let path = output.with_extension("txt");
+                let mut sidecar = File::create(&path)?;
+                exporter.export_debug_notes(&mut sidecar, ast.clone())?;
+                writeln!(log, "Exported to {}", path.display())?;
+            }
+            exporter.export(&mut out, ast)?;
+            writeln!(log, "Exported to {}", output.display())?;
+        }
+        "json" => {
+            ChordTrackJsonExporter.export(&mut out, ast)?;
+            writeln!(log, "Exported to {}", output.display())?;
+        }
+        "rb" => {
+            SonicPiExporter { key: args.key }.export(&mut out, ast)?;
+            writeln!(log, "Exported to {}", output.display())?;
         }
         _ => {
-            RechordExporter.export(&mut out, ast)?;
-            println!("Exported to {}", args.output.display());
+            RechordExporter {
+                measures_per_line: args.measures_per_line,
+                align: args.align,
+            }
+            .export(&mut out, ast)?;
+            writeln!(log, "Exported to {}", output.display())?;
         }
     };
     Ok(())
 }
+
+fn watch(args: &Cli) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    // editors that save by renaming replace the file, so its directory is watched
+    let dir = match args.input.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = args.input.file_name();
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    println!("Watching {}", args.input.display());
+    loop {
+        if let Err(e) = render(args, &mut io::stdout()) {
+            eprintln!("{:?}", e);
+        }
+        // wait for a change to the input, then drain the burst editors emit on save
+        loop {
+            match rx.recv()? {
+                Ok(event) if event.paths.iter().any(|p| p.file_name() == name) => break,
+                Ok(_) => {}
+                Err(e) => eprintln!("{:?}", e),
+            }
+        }
+        while rx.recv_timeout(DEBOUNCE).is_ok() {}
+    }
+}
+
+fn main() -> Result<()> {
+    simplelog::SimpleLogger::init(log::LevelFilter::Debug, Default::default())?;
+    let args = Cli::try_parse()?;
+    if let Some(Command::Analyze) = args.command {
+        analyze(&args)
+    } else if let Some(Command::TransposeAll) = args.command {
+        transpose_all(&args, &mut io::stdout())
+    } else if args.watch {
+        watch(&args)
+    } else {
+        render(&args, &mut io::stdout())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{histogram, import, render, transpose_all, Cli};
+    use anyhow::Result;
+    use chord_midi::import::{Importer, RechordImporter};
+    use clap::Parser as _;
+    use std::{
+        fs,
+        ops::Deref,
+        path::{Path, PathBuf},
+    };
+
+    /// a directory under the system's temp dir, removed when dropped so failing tests clean up too
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Result<Self> {
+            let dir =
+                std::env::temp_dir().join(format!("chord_midi_{}_{}", name, std::process::id()));
+            fs::create_dir_all(&dir)?;
+            Ok(TempDir(dir))
+        }
+    }
+
+    impl Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let dir = TempDir::new("render")?;
+        let input = dir.join("song.txt");
+        let output = dir.join("out.txt");
+        let args = Cli::try_parse_from([
+            "chord_midi_cli".as_ref(),
+            "-i".as_ref(),
+            input.as_os_str(),
+            "-o".as_ref(),
+            output.as_os_str(),
+        ])?;
+
+        fs::write(&input, "C G | Am F\r\n")?;
+        render(&args, &mut vec![])?;
+        assert_eq!(fs::read_to_string(&output)?, "C G | Am F | \n\n");
+
+        // a broken chart is reported without touching the previous output
+        fs::write(&input, "C G | ???\n")?;
+        assert!(render(&args, &mut vec![]).is_err());
+        assert_eq!(fs::read_to_string(&output)?, "C G | Am F | \n\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_swing_range() -> Result<()> {
+        let parse =
+            |swing| Cli::try_parse_from(["chord_midi_cli", "-i", "in.txt", "--swing", swing]);
+        assert_eq!(parse("0.67")?.swing, 0.67);
+        for swing in ["0", "1", "1.5", "-0.5", "NaN"] {
+            assert!(parse(swing).is_err(), "{}", swing);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_node_length_range() -> Result<()> {
+        let parse = |n| Cli::try_parse_from(["chord_midi_cli", "-i", "in.txt", "--node-length", n]);
+        assert_eq!(parse("4")?.node_length, Some(4));
+        assert!(parse("0").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_print_ast() -> Result<()> {
+        let dir = TempDir::new("print_ast")?;
+        let input = dir.join("song.txt");
+        let output = dir.join("out.txt");
+        let args = Cli::try_parse_from([
+            "chord_midi_cli".as_ref(),
+            "-i".as_ref(),
+            input.as_os_str(),
+            "-o".as_ref(),
+            output.as_os_str(),
+            "--print-ast".as_ref(),
+        ])?;
+
+        fs::write(&input, "C G | Am F\n")?;
+        let mut log = vec![];
+        render(&args, &mut log)?;
+        let log = String::from_utf8(log)?;
+        assert!(log.contains("Measure"));
+        assert!(log.ends_with(&format!("Exported to {}\n", output.display())));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_analyze() -> Result<()> {
+        let dir = TempDir::new("analyze")?;
+        let input = dir.join("song.txt");
+        let args = Cli::try_parse_from([
+            "chord_midi_cli".as_ref(),
+            "-i".as_ref(),
+            input.as_os_str(),
+            "analyze".as_ref(),
+        ])?;
+
+        fs::write(&input, "C G | Am F | C G | C\n")?;
+        let histogram = histogram(&args, import(&args)?)?
+            .into_iter()
+            .map(|(chord, count, beats)| (chord.to_string(), count, beats))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            histogram,
+            vec![
+                ("C".to_string(), 3, 8.0),
+                ("G".to_string(), 2, 4.0),
+                ("Am".to_string(), 1, 2.0),
+                ("F".to_string(), 1, 2.0),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transpose_all() -> Result<()> {
+        let dir = TempDir::new("transpose")?;
+        let input = dir.join("song.txt");
+        let output = dir.join("keys.txt");
+        let args = Cli::try_parse_from([
+            "chord_midi_cli".as_ref(),
+            "-i".as_ref(),
+            input.as_os_str(),
+            "-o".as_ref(),
+            output.as_os_str(),
+            "--key".as_ref(),
+            "D".as_ref(),
+            "transpose-all".as_ref(),
+        ])?;
+
+        fs::write(&input, "D A | Bm G\n")?;
+        let mut log = vec![];
+        transpose_all(&args, &mut log)?;
+        assert_eq!(
+            String::from_utf8(log)?,
+            format!("Exported to {}\n", output.display())
+        );
+        let keys = fs::read_to_string(&output)?;
+        assert_eq!(keys.matches("# Key: ").count(), 12);
+        assert!(keys.starts_with("# Key: D\nD A | Bm G"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_medley() -> Result<()> {
+        let dir = TempDir::new("medley")?;
+        let charts = dir.join("charts");
+        fs::create_dir_all(&charts)?;
+        fs::write(charts.join("2_outro.txt"), "F G | C\n")?;
+        fs::write(charts.join("1_intro.txt"), "C G | Am F\n")?;
+        fs::write(charts.join(".notes.txt"), "???\n")?;
+        let output = dir.join("medley.midi");
+        let args = Cli::try_parse_from([
+            "chord_midi_cli".as_ref(),
+            "-i".as_ref(),
+            charts.as_os_str(),
+            "-o".as_ref(),
+            output.as_os_str(),
+        ])?;
+
+        let expected =
+            RechordImporter::default().import("[1_intro]\nC G | Am F\n[2_outro]\nF G | C\n")?;
+        assert_eq!(import(&args)?.to_string(), expected.to_string());
+        render(&args, &mut vec![])?;
+        let midi = fs::read(&output)?;
+        assert!(midi.starts_with(b"MThd"));
+        let intro = midi.windows(7).position(|w| w == b"1_intro");
+        let outro = midi.windows(7).position(|w| w == b"2_outro");
+        assert!(intro.is_some() && intro < outro);
+
+        Ok(())
+    }
+}
@@ -1,8 +1,10 @@
 use chord_midi::{
     export::{Exporter, MidiExporter},
     import::{Importer, RechordImporter},
+    model::{ast::ChordNode, pitch::Pitch},
 };
 use std::{io::BufWriter, ops::Deref};
+use wasm_bindgen::prelude::wasm_bindgen;
 use web_sys::{
     wasm_bindgen::{JsCast, JsValue},
     Blob,
@@ -28,6 +30,17 @@ fn new_blob(arr: &[u8], mime_type: &str) -> Blob {
     Blob::new_with_blob_sequence_and_options(&parts, &options).unwrap()
 }
 
+/// returns note names, pitch-class set, intervals and suggested scale of a single chord symbol,
+/// throwing the parse error to JavaScript
+#[wasm_bindgen]
+pub fn chord_info(symbol: &str) -> Result<JsValue, JsValue> {
+    let info = ChordNode::try_from(symbol)
+        .and_then(|chord| chord.to_chord())
+        .and_then(|chord| chord.info())
+        .map_err(|err| JsValue::from(format!("{}", err)))?;
+    Ok(serde_wasm_bindgen::to_value(&info)?)
+}
+
 #[function_component(App)]
 fn app() -> Html {
     let result_state = use_state(|| String::new());
@@ -43,7 +56,7 @@ fn app() -> Html {
                 .value()
                 + "\n";
             web_sys::console::log_1(&JsValue::from(format!("{}", input)));
-            let res = RechordImporter.import(&input);
+            let res = RechordImporter::default().import(&input);
             if let Err(err) = res {
                 error_state.set(format!("{}", err));
                 web_sys::console::log_1(&JsValue::from(format!("{}", err)));
@@ -53,7 +66,11 @@ fn app() -> Html {
             result.set(format!("{}", ast));
 
             let mut writer = BufWriter::new(Vec::new());
-            MidiExporter { bpm: 120 }.export(&mut writer, ast).unwrap();
+            MidiExporter::builder()
+                .key(Pitch::C)
+                .build()
+                .export(&mut writer, ast)
+                .unwrap();
 
             let blob = new_blob(writer.buffer(), "audio/midi");
             let object_url = web_sys::Url::create_object_url_with_blob(&blob).unwrap();
@@ -80,3 +97,18 @@ fn app() -> Html {
 fn main() {
     yew::Renderer::<App>::new().render();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::chord_info;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn test_chord_info() {
+        let info = chord_info("Cm7").unwrap();
+        let notes = web_sys::js_sys::Reflect::get(&info, &"notes".into()).unwrap();
+        let notes: Vec<String> = serde_wasm_bindgen::from_value(notes).unwrap();
+        assert_eq!(notes, vec!["C", "D#", "G", "A#"]);
+        assert!(chord_info("Czyx").unwrap_err().is_string());
+    }
+}